@@ -0,0 +1,134 @@
+use crate::auth::{
+    AuthWithBotToken, AuthWithPhoneAndCode, CodeDelivery, SignInFailure, SignInRetry,
+};
+use crate::Result;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use std::time::Duration;
+use tokio::time::sleep;
+
+const CODE_FILE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+fn read_env(name: &str) -> Result<String> {
+    match std::env::var(name) {
+        Ok(value) if !value.trim().is_empty() => Ok(value.trim().to_owned()),
+        _ => Err(anyhow!("env {name} is not set")),
+    }
+}
+
+/// Read the bot token from an environment variable
+pub struct EnvBotToken {
+    pub name: String,
+}
+
+impl Default for EnvBotToken {
+    fn default() -> Self {
+        Self::new("TELESER_BOT_TOKEN")
+    }
+}
+
+impl EnvBotToken {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self { name: name.into() }
+    }
+}
+
+#[async_trait]
+impl AuthWithBotToken for EnvBotToken {
+    async fn input_bot_token(&self) -> Result<String> {
+        read_env(&self.name)
+    }
+}
+
+/// Read the phone number and password from environment variables and the code from a file,
+/// for unattended deployments.
+///
+/// The code is new on every login, so it can not be passed when starting the process:
+/// once the code is sent, write it to the file named by `TELESER_CODE_FILE`.
+/// The file is removed before sending and after reading, and a wrong code can be written again.
+pub struct EnvPhoneAndCode {
+    /// Names of the variables, not the values
+    pub phone_var: String,
+    pub code_file_var: String,
+    pub password_var: String,
+}
+
+impl Default for EnvPhoneAndCode {
+    fn default() -> Self {
+        Self {
+            phone_var: "TELESER_PHONE".to_owned(),
+            code_file_var: "TELESER_CODE_FILE".to_owned(),
+            password_var: "TELESER_PASSWORD".to_owned(),
+        }
+    }
+}
+
+impl EnvPhoneAndCode {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Name of the variable holding the phone number
+    pub fn with_phone<S: Into<String>>(mut self, name: S) -> Self {
+        self.phone_var = name.into();
+        self
+    }
+
+    /// Name of the variable holding the path of the code file
+    pub fn with_code_file<S: Into<String>>(mut self, name: S) -> Self {
+        self.code_file_var = name.into();
+        self
+    }
+
+    /// Name of the variable holding the password
+    pub fn with_password<S: Into<String>>(mut self, name: S) -> Self {
+        self.password_var = name.into();
+        self
+    }
+}
+
+async fn remove_code_file(path: &str) -> Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+#[async_trait]
+impl AuthWithPhoneAndCode for EnvPhoneAndCode {
+    async fn input_phone(&self) -> Result<String> {
+        read_env(&self.phone_var)
+    }
+
+    async fn input_code(&self) -> Result<String> {
+        let path = read_env(&self.code_file_var)?;
+        tracing::info!("waiting for the login code in {path}");
+        loop {
+            match tokio::fs::read_to_string(&path).await {
+                Ok(code) if !code.trim().is_empty() => {
+                    remove_code_file(&path).await?;
+                    return Ok(code.trim().to_owned());
+                }
+                _ => sleep(CODE_FILE_POLL_INTERVAL).await,
+            }
+        }
+    }
+
+    async fn input_password(&self) -> Result<String> {
+        read_env(&self.password_var)
+    }
+
+    async fn on_code_sent(&self, _delivery: &CodeDelivery) -> Result<()> {
+        // a code left by an earlier login is not valid anymore
+        remove_code_file(&read_env(&self.code_file_var)?).await
+    }
+
+    async fn on_sign_in_failure(&self, failure: &SignInFailure) -> Result<SignInRetry> {
+        Ok(match failure {
+            SignInFailure::InvalidCode | SignInFailure::FloodWait(_) => SignInRetry::Retry,
+            SignInFailure::ExpiredCode => SignInRetry::ResendCode,
+            // the variables would not change
+            _ => SignInRetry::Abort,
+        })
+    }
+}
@@ -0,0 +1,519 @@
+mod env;
+#[cfg(feature = "terminal")]
+mod terminal;
+
+pub use env::*;
+#[cfg(feature = "terminal")]
+pub use terminal::*;
+
+use crate::{Client, Result};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use grammers_client::types::{PasswordToken, User};
+use grammers_client::{InvocationError, SignInError};
+use grammers_session::Session;
+use grammers_tl_types as tl;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::sleep;
+
+const QR_POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+pub enum Auth {
+    AuthWithBotToken(Box<dyn AuthWithBotToken + Send + Sync>),
+    AuthWithPhoneAndCode(Box<dyn AuthWithPhoneAndCode + Send + Sync>),
+    AuthWithQrCode(Box<dyn AuthWithQrCode + Send + Sync>),
+}
+
+#[async_trait]
+pub trait AuthWithBotToken {
+    async fn input_bot_token(&self) -> Result<String>;
+}
+
+#[async_trait]
+pub trait AuthWithPhoneAndCode {
+    async fn input_phone(&self) -> Result<String>;
+    async fn input_code(&self) -> Result<String>;
+    async fn input_password(&self) -> Result<String>;
+
+    /// How many failed steps are retried before the login gives up
+    fn max_retries(&self) -> u32 {
+        3
+    }
+
+    /// Called when the code was sent (or sent again), tells where to look for it
+    async fn on_code_sent(&self, _delivery: &CodeDelivery) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called when the phone number has no account yet, return the name to sign up with.
+    /// Defaults to not sign up.
+    async fn sign_up(&self) -> Result<Option<SignUpInfo>> {
+        Ok(None)
+    }
+
+    /// Called before signing up with the terms of service to accept,
+    /// returning false cancels the sign up
+    async fn accept_terms_of_service(
+        &self,
+        _terms_of_service: &tl::types::help::TermsOfService,
+    ) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Called when a step fails, decides what to do next.
+    /// Defaults to resend an expired code and retry on other failures.
+    async fn on_sign_in_failure(&self, failure: &SignInFailure) -> Result<SignInRetry> {
+        Ok(match failure {
+            SignInFailure::ExpiredCode => SignInRetry::ResendCode,
+            _ => SignInRetry::Retry,
+        })
+    }
+}
+
+/// The profile of a new account
+#[derive(Debug, Clone)]
+pub struct SignUpInfo {
+    pub first_name: String,
+    pub last_name: String,
+}
+
+/// Where the login code was sent
+#[derive(Debug, Clone)]
+pub struct CodeDelivery {
+    pub code_type: tl::enums::auth::SentCodeType,
+    /// The method used by `SignInRetry::ResendCode`, if any
+    pub next_type: Option<tl::enums::auth::CodeType>,
+    /// Wait before resending the code
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+pub enum SignInFailure {
+    InvalidPhone,
+    InvalidCode,
+    ExpiredCode,
+    InvalidPassword { hint: Option<String> },
+    FloodWait(Duration),
+}
+
+impl std::fmt::Display for SignInFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignInFailure::InvalidPhone => write!(f, "invalid phone number"),
+            SignInFailure::InvalidCode => write!(f, "invalid code"),
+            SignInFailure::ExpiredCode => write!(f, "expired code"),
+            SignInFailure::InvalidPassword { hint: Some(hint) } => {
+                write!(f, "invalid password (hint: {hint})")
+            }
+            SignInFailure::InvalidPassword { hint: None } => write!(f, "invalid password"),
+            SignInFailure::FloodWait(wait) => write!(f, "flood wait {} sec", wait.as_secs()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignInRetry {
+    /// Input the failed step again (after waiting on flood wait)
+    Retry,
+    /// Send the code again with the next method, then input the code again
+    ResendCode,
+    Abort,
+}
+
+#[async_trait]
+pub trait AuthWithQrCode {
+    /// Called with a `tg://login?token=...` url, render it as a QR code and scan it with
+    /// a logged in app (Settings > Devices > Link Desktop Device).
+    /// Called again with a new url when the previous one expires.
+    async fn on_login_url(&self, url: &str, expires: SystemTime) -> Result<()>;
+    async fn input_password(&self) -> Result<String>;
+}
+
+pub struct StaticBotToken {
+    pub token: String,
+}
+
+#[async_trait]
+impl AuthWithBotToken for StaticBotToken {
+    async fn input_bot_token(&self) -> Result<String> {
+        return Ok(self.token.clone());
+    }
+}
+
+/// Sign in with the `Auth` of the client,
+/// returns the inner client to use from now on (QR login may move to another data center)
+pub(crate) async fn login(
+    client: &Client,
+    inner_client: grammers_client::Client,
+) -> Result<(grammers_client::Client, User)> {
+    let usr = match &client.auth {
+        Auth::AuthWithPhoneAndCode(auth) => {
+            return phone_login(client, inner_client, auth.as_ref()).await
+        }
+        Auth::AuthWithBotToken(auth) => {
+            inner_client
+                .bot_sign_in(auth.input_bot_token().await?.as_str())
+                .await?
+        }
+        Auth::AuthWithQrCode(auth) => return qr_login(client, inner_client, auth.as_ref()).await,
+    };
+    Ok((inner_client, usr))
+}
+
+fn sign_in_failure(err: &InvocationError) -> Option<SignInFailure> {
+    match err {
+        InvocationError::Rpc(rpc) if rpc.is("FLOOD_WAIT") => Some(SignInFailure::FloodWait(
+            Duration::from_secs(rpc.value.unwrap_or_default() as u64),
+        )),
+        _ if err.is("PHONE_NUMBER_INVALID") => Some(SignInFailure::InvalidPhone),
+        _ if err.is("PHONE_CODE_EXPIRED") => Some(SignInFailure::ExpiredCode),
+        _ if err.is("PHONE_CODE_*") => Some(SignInFailure::InvalidCode),
+        _ => None,
+    }
+}
+
+/// Ask what to do with a failure, returns an error if the login should give up
+async fn on_failure(
+    auth: &(dyn AuthWithPhoneAndCode + Send + Sync),
+    failure: SignInFailure,
+    failures: &mut u32,
+) -> Result<SignInRetry> {
+    *failures += 1;
+    tracing::warn!("sign in failed ({}) : {failure}", *failures);
+    let retry = auth.on_sign_in_failure(&failure).await?;
+    if retry == SignInRetry::Abort || *failures > auth.max_retries() {
+        return Err(anyhow!("sign in failed : {failure}"));
+    }
+    if let SignInFailure::FloodWait(wait) = failure {
+        sleep(wait).await;
+    }
+    Ok(retry)
+}
+
+fn code_delivery(sent_code: &tl::types::auth::SentCode) -> CodeDelivery {
+    CodeDelivery {
+        code_type: sent_code.r#type.clone(),
+        next_type: sent_code.next_type.clone(),
+        timeout: sent_code
+            .timeout
+            .map(|timeout| Duration::from_secs(timeout.max(0) as u64)),
+    }
+}
+
+enum SentCode {
+    Code(tl::types::auth::SentCode),
+    Authorization(Box<tl::enums::auth::Authorization>),
+}
+
+impl From<tl::enums::auth::SentCode> for SentCode {
+    fn from(sent_code: tl::enums::auth::SentCode) -> Self {
+        match sent_code {
+            tl::enums::auth::SentCode::Code(code) => SentCode::Code(code),
+            tl::enums::auth::SentCode::Success(success) => {
+                SentCode::Authorization(Box::new(success.authorization))
+            }
+        }
+    }
+}
+
+/// Send the login code, moving to the data center of the phone number if asked
+async fn send_code(
+    client: &Client,
+    inner_client: &mut grammers_client::Client,
+    phone: &str,
+) -> Result<SentCode> {
+    let request = tl::functions::auth::SendCode {
+        phone_number: phone.to_owned(),
+        api_id: client.api_id,
+        api_hash: client.api_hash.clone(),
+        settings: tl::types::CodeSettings {
+            allow_flashcall: false,
+            current_number: false,
+            allow_app_hash: false,
+            allow_missed_call: false,
+            allow_firebase: false,
+            logout_tokens: None,
+            token: None,
+            app_sandbox: None,
+            unknown_number: false,
+        }
+        .into(),
+    };
+    match inner_client.invoke(&request).await {
+        Err(InvocationError::Rpc(err)) if err.code == 303 => {
+            let dc_id = err.value.unwrap_or_default() as i32;
+            tracing::info!("phone login migrate to dc {dc_id}");
+            *inner_client = connect_to_dc(client, dc_id).await?;
+            Ok(inner_client.invoke(&request).await?.into())
+        }
+        result => Ok(result?.into()),
+    }
+}
+
+async fn phone_login(
+    client: &Client,
+    mut inner_client: grammers_client::Client,
+    auth: &(dyn AuthWithPhoneAndCode + Send + Sync),
+) -> Result<(grammers_client::Client, User)> {
+    let mut failures = 0;
+    let (phone, mut sent_code) = loop {
+        let phone = auth.input_phone().await?;
+        match send_code(client, &mut inner_client, &phone).await {
+            Ok(SentCode::Code(sent_code)) => break (phone, sent_code),
+            Ok(SentCode::Authorization(authorization)) => {
+                return complete_login(client, inner_client, *authorization).await;
+            }
+            Err(err) => match err.downcast_ref().and_then(sign_in_failure) {
+                Some(failure) => {
+                    on_failure(auth, failure, &mut failures).await?;
+                }
+                None => return Err(err),
+            },
+        }
+    };
+    auth.on_code_sent(&code_delivery(&sent_code)).await?;
+    loop {
+        let code = auth.input_code().await?;
+        let signed_in = inner_client
+            .invoke(&tl::functions::auth::SignIn {
+                phone_number: phone.clone(),
+                phone_code_hash: sent_code.phone_code_hash.clone(),
+                phone_code: Some(code),
+                email_verification: None,
+            })
+            .await;
+        let err = match signed_in {
+            Ok(tl::enums::auth::Authorization::SignUpRequired(required)) => {
+                let terms_of_service = required.terms_of_service.map(|terms| match terms {
+                    tl::enums::help::TermsOfService::Service(terms) => terms,
+                });
+                return phone_sign_up(
+                    client,
+                    inner_client,
+                    auth,
+                    &phone,
+                    &sent_code.phone_code_hash,
+                    terms_of_service,
+                )
+                .await;
+            }
+            Ok(authorization) => return complete_login(client, inner_client, authorization).await,
+            Err(err) if err.is("SESSION_PASSWORD_NEEDED") => {
+                return phone_password(client, inner_client, auth, failures).await;
+            }
+            Err(err) => err,
+        };
+        let failure = match sign_in_failure(&err) {
+            Some(failure) => failure,
+            None => return Err(err.into()),
+        };
+        if on_failure(auth, failure, &mut failures).await? == SignInRetry::ResendCode {
+            let resent = match inner_client
+                .invoke(&tl::functions::auth::ResendCode {
+                    phone_number: phone.clone(),
+                    phone_code_hash: sent_code.phone_code_hash.clone(),
+                    reason: None,
+                })
+                .await
+            {
+                Ok(resent) => resent.into(),
+                // the code hash expired too, start over with a new code
+                Err(err) if err.is("PHONE_CODE_EXPIRED") => {
+                    send_code(client, &mut inner_client, &phone).await?
+                }
+                Err(err) => return Err(err.into()),
+            };
+            match resent {
+                SentCode::Code(resent) => sent_code = resent,
+                SentCode::Authorization(authorization) => {
+                    return complete_login(client, inner_client, *authorization).await;
+                }
+            }
+            auth.on_code_sent(&code_delivery(&sent_code)).await?;
+        }
+    }
+}
+
+async fn phone_sign_up(
+    client: &Client,
+    inner_client: grammers_client::Client,
+    auth: &(dyn AuthWithPhoneAndCode + Send + Sync),
+    phone: &str,
+    phone_code_hash: &str,
+    terms_of_service: Option<tl::types::help::TermsOfService>,
+) -> Result<(grammers_client::Client, User)> {
+    let info = auth
+        .sign_up()
+        .await?
+        .ok_or_else(|| anyhow!("phone number is not registered, sign up is disabled"))?;
+    if let Some(terms_of_service) = &terms_of_service {
+        if !auth.accept_terms_of_service(terms_of_service).await? {
+            return Err(anyhow!("terms of service not accepted"));
+        }
+    }
+    let authorization = inner_client
+        .invoke(&tl::functions::auth::SignUp {
+            no_joined_notifications: false,
+            phone_number: phone.to_owned(),
+            phone_code_hash: phone_code_hash.to_owned(),
+            first_name: info.first_name,
+            last_name: info.last_name,
+        })
+        .await?;
+    let (inner_client, usr) = complete_login(client, inner_client, authorization).await?;
+    if let Some(terms_of_service) = terms_of_service {
+        inner_client
+            .invoke(&tl::functions::help::AcceptTermsOfService {
+                id: terms_of_service.id,
+            })
+            .await?;
+    }
+    tracing::info!("signed up new account {}", usr.id());
+    Ok((inner_client, usr))
+}
+
+async fn phone_password(
+    client: &Client,
+    inner_client: grammers_client::Client,
+    auth: &(dyn AuthWithPhoneAndCode + Send + Sync),
+    mut failures: u32,
+) -> Result<(grammers_client::Client, User)> {
+    loop {
+        let tl::enums::account::Password::Password(password) = inner_client
+            .invoke(&tl::functions::account::GetPassword {})
+            .await?;
+        let token = PasswordToken::new(password);
+        let hint = token.hint().map(str::to_owned);
+        let err = match inner_client
+            .check_password(token, auth.input_password().await?.as_str())
+            .await
+        {
+            Ok(usr) => {
+                client.save_session(&inner_client).await?;
+                return Ok((inner_client, usr));
+            }
+            Err(SignInError::InvalidPassword) => {
+                on_failure(auth, SignInFailure::InvalidPassword { hint }, &mut failures).await?;
+                continue;
+            }
+            Err(SignInError::Other(err)) => err,
+            Err(err) => return Err(anyhow!(err)),
+        };
+        match sign_in_failure(&err) {
+            Some(failure) => {
+                on_failure(auth, failure, &mut failures).await?;
+            }
+            None => return Err(err.into()),
+        }
+    }
+}
+
+async fn connect_to_dc(client: &Client, dc_id: i32) -> Result<grammers_client::Client> {
+    let session = Session::new();
+    // the user is unknown yet, it only tells which data center to connect
+    session.set_user(0, dc_id, false);
+    client.connect_with_session(session).await
+}
+
+async fn qr_login(
+    client: &Client,
+    mut inner_client: grammers_client::Client,
+    auth: &(dyn AuthWithQrCode + Send + Sync),
+) -> Result<(grammers_client::Client, User)> {
+    let mut last_token = None;
+    loop {
+        let exported = inner_client
+            .invoke(&tl::functions::auth::ExportLoginToken {
+                api_id: client.api_id,
+                api_hash: client.api_hash.clone(),
+                except_ids: vec![],
+            })
+            .await;
+        let login_token = match exported {
+            Ok(login_token) => login_token,
+            Err(err) if err.is("SESSION_PASSWORD_NEEDED") => {
+                return qr_password(client, inner_client, auth).await;
+            }
+            Err(err) => return Err(err.into()),
+        };
+        match login_token {
+            tl::enums::auth::LoginToken::Token(token) => {
+                if last_token.as_ref() != Some(&token.token) {
+                    let url = format!("tg://login?token={}", URL_SAFE_NO_PAD.encode(&token.token));
+                    let expires = UNIX_EPOCH + Duration::from_secs(token.expires.max(0) as u64);
+                    auth.on_login_url(&url, expires).await?;
+                    last_token = Some(token.token);
+                }
+                sleep(QR_POLL_INTERVAL).await;
+            }
+            tl::enums::auth::LoginToken::MigrateTo(migrate) => {
+                tracing::info!("qr login migrate to dc {}", migrate.dc_id);
+                inner_client = connect_to_dc(client, migrate.dc_id).await?;
+                let imported = inner_client
+                    .invoke(&tl::functions::auth::ImportLoginToken {
+                        token: migrate.token,
+                    })
+                    .await;
+                match imported {
+                    Ok(tl::enums::auth::LoginToken::Success(success)) => {
+                        return complete_login(client, inner_client, success.authorization).await;
+                    }
+                    Ok(_) => return Err(anyhow!("qr login token not accepted after migration")),
+                    Err(err) if err.is("SESSION_PASSWORD_NEEDED") => {
+                        return qr_password(client, inner_client, auth).await;
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            tl::enums::auth::LoginToken::Success(success) => {
+                return complete_login(client, inner_client, success.authorization).await;
+            }
+        }
+    }
+}
+
+async fn qr_password(
+    client: &Client,
+    inner_client: grammers_client::Client,
+    auth: &(dyn AuthWithQrCode + Send + Sync),
+) -> Result<(grammers_client::Client, User)> {
+    let tl::enums::account::Password::Password(password) = inner_client
+        .invoke(&tl::functions::account::GetPassword {})
+        .await?;
+    let usr = inner_client
+        .check_password(
+            PasswordToken::new(password),
+            auth.input_password().await?.as_str(),
+        )
+        .await?;
+    client.save_session(&inner_client).await?;
+    Ok((inner_client, usr))
+}
+
+/// Store the user of the raw authorization,
+/// then reconnect so the inner client starts clean as a logged in client
+async fn complete_login(
+    client: &Client,
+    inner_client: grammers_client::Client,
+    authorization: tl::enums::auth::Authorization,
+) -> Result<(grammers_client::Client, User)> {
+    let authorization = match authorization {
+        tl::enums::auth::Authorization::Authorization(authorization) => authorization,
+        tl::enums::auth::Authorization::SignUpRequired(_) => {
+            return Err(anyhow!("login requires sign up"))
+        }
+    };
+    let usr = User::from_raw(authorization.user);
+    let tl::enums::Config::Config(config) = inner_client
+        .invoke(&tl::functions::help::GetConfig {})
+        .await?;
+    inner_client
+        .session()
+        .set_user(usr.id(), config.this_dc, usr.is_bot());
+    client.save_session(&inner_client).await?;
+    // from memory, the store may not give back what was just saved
+    let session = Session::load(&inner_client.session().save())?;
+    Ok((client.connect_with_session(session).await?, usr))
+}
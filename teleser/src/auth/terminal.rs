@@ -0,0 +1,164 @@
+use crate::auth::{
+    AuthWithBotToken, AuthWithPhoneAndCode, AuthWithQrCode, CodeDelivery, SignInFailure,
+    SignInRetry, SignUpInfo,
+};
+use crate::Result;
+use async_trait::async_trait;
+use grammers_tl_types as tl;
+use std::io::{stdin, stdout, Write};
+use std::time::SystemTime;
+
+/// Ask the login inputs on the terminal, the password and the bot token are not echoed
+pub struct TerminalAuth {
+    pub phone_prompt: String,
+    pub code_prompt: String,
+    pub password_prompt: String,
+    pub bot_token_prompt: String,
+    /// Ask the name of a new account when the phone number is not registered
+    pub sign_up: bool,
+}
+
+impl Default for TerminalAuth {
+    fn default() -> Self {
+        Self {
+            phone_prompt: "Input your phone number ( like +112345678 )".to_owned(),
+            code_prompt: "Input your device or sms code ( like 12345 )".to_owned(),
+            password_prompt: "Input your password".to_owned(),
+            bot_token_prompt: "Input your bot token".to_owned(),
+            sign_up: false,
+        }
+    }
+}
+
+impl TerminalAuth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_phone_prompt<S: Into<String>>(mut self, prompt: S) -> Self {
+        self.phone_prompt = prompt.into();
+        self
+    }
+
+    pub fn with_code_prompt<S: Into<String>>(mut self, prompt: S) -> Self {
+        self.code_prompt = prompt.into();
+        self
+    }
+
+    pub fn with_password_prompt<S: Into<String>>(mut self, prompt: S) -> Self {
+        self.password_prompt = prompt.into();
+        self
+    }
+
+    pub fn with_bot_token_prompt<S: Into<String>>(mut self, prompt: S) -> Self {
+        self.bot_token_prompt = prompt.into();
+        self
+    }
+
+    pub fn with_sign_up(mut self, sign_up: bool) -> Self {
+        self.sign_up = sign_up;
+        self
+    }
+}
+
+/// Read a line from the terminal without blocking the runtime
+pub async fn read_line(prompt: &str) -> Result<String> {
+    let prompt = prompt.to_owned();
+    tokio::task::spawn_blocking(move || {
+        let mut s = String::new();
+        print!("{prompt}: ");
+        let _ = stdout().flush();
+        stdin().read_line(&mut s)?;
+        Ok(s.trim_end_matches(['\r', '\n']).to_owned())
+    })
+    .await?
+}
+
+/// Read a line from the terminal without echo
+pub async fn read_hidden(prompt: &str) -> Result<String> {
+    let prompt = format!("{prompt}: ");
+    Ok(tokio::task::spawn_blocking(move || rpassword::prompt_password(prompt)).await??)
+}
+
+#[async_trait]
+impl AuthWithPhoneAndCode for TerminalAuth {
+    async fn input_phone(&self) -> Result<String> {
+        read_line(&self.phone_prompt).await
+    }
+
+    async fn input_code(&self) -> Result<String> {
+        read_line(&self.code_prompt).await
+    }
+
+    async fn input_password(&self) -> Result<String> {
+        read_hidden(&self.password_prompt).await
+    }
+
+    async fn on_code_sent(&self, delivery: &CodeDelivery) -> Result<()> {
+        let by = match &delivery.code_type {
+            tl::enums::auth::SentCodeType::App(_) => "the telegram app",
+            tl::enums::auth::SentCodeType::Sms(_)
+            | tl::enums::auth::SentCodeType::SmsWord(_)
+            | tl::enums::auth::SentCodeType::SmsPhrase(_)
+            | tl::enums::auth::SentCodeType::FirebaseSms(_) => "sms",
+            tl::enums::auth::SentCodeType::FragmentSms(_) => "fragment",
+            tl::enums::auth::SentCodeType::Call(_) => "phone call",
+            tl::enums::auth::SentCodeType::EmailCode(_) => "email",
+            _ => "another method",
+        };
+        println!("The code was sent by {by}");
+        Ok(())
+    }
+
+    async fn sign_up(&self) -> Result<Option<SignUpInfo>> {
+        if !self.sign_up {
+            return Ok(None);
+        }
+        println!("The phone number is not registered, sign up a new account");
+        Ok(Some(SignUpInfo {
+            first_name: read_line("Input your first name").await?,
+            last_name: read_line("Input your last name (optional)").await?,
+        }))
+    }
+
+    async fn accept_terms_of_service(
+        &self,
+        terms_of_service: &tl::types::help::TermsOfService,
+    ) -> Result<bool> {
+        println!("{}", terms_of_service.text);
+        let answer = read_line("Accept the terms of service (y/N)").await?;
+        Ok(answer.trim().eq_ignore_ascii_case("y"))
+    }
+
+    async fn on_sign_in_failure(&self, failure: &SignInFailure) -> Result<SignInRetry> {
+        println!("Sign in failed : {failure}");
+        Ok(match failure {
+            SignInFailure::ExpiredCode => SignInRetry::ResendCode,
+            _ => SignInRetry::Retry,
+        })
+    }
+}
+
+#[async_trait]
+impl AuthWithQrCode for TerminalAuth {
+    async fn on_login_url(&self, url: &str, expires: SystemTime) -> Result<()> {
+        let secs = expires
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+            .as_secs();
+        println!("Scan the QR code of this url with a logged in app (expires in {secs} sec)");
+        println!("{url}");
+        Ok(())
+    }
+
+    async fn input_password(&self) -> Result<String> {
+        read_hidden(&self.password_prompt).await
+    }
+}
+
+#[async_trait]
+impl AuthWithBotToken for TerminalAuth {
+    async fn input_bot_token(&self) -> Result<String> {
+        read_hidden(&self.bot_token_prompt).await
+    }
+}
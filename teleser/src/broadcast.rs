@@ -0,0 +1,301 @@
+use crate::session::write_atomic;
+use crate::{ClientHandle, Result, SendPriority};
+use anyhow::anyhow;
+use grammers_client::{InputMessage, InvocationError};
+use grammers_session::PackedChat;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+
+/// How a broadcast sends
+#[derive(Debug, Clone)]
+pub struct BroadcastOptions {
+    /// Sends between two pauses of `batch_delay`
+    pub batch_size: usize,
+    pub batch_delay: Duration,
+    /// File saving the progress after every batch and on pause or cancel, a broadcast
+    /// started again with the same file continues from there (after a crash, the sends
+    /// of the last batch are sent again). Removed when the broadcast finishes.
+    /// A checkpoint of other chats or another `message_key` is rejected.
+    pub checkpoint: Option<PathBuf>,
+    /// Identifies the message in the checkpoint, like its text or a version,
+    /// since the content of an `InputMessage` can't be read back
+    pub message_key: Option<String>,
+}
+
+impl Default for BroadcastOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 20,
+            batch_delay: Duration::from_secs(1),
+            checkpoint: None,
+            message_key: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BroadcastState {
+    Running,
+    Paused,
+    Cancelled,
+    Finished,
+}
+
+#[derive(Debug, Clone)]
+pub struct BroadcastProgress {
+    pub total: usize,
+    /// Index of the next chat to send to
+    pub next: usize,
+    pub sent: usize,
+    pub failed: usize,
+    /// The user blocked the bot, was deleted, or the chat can't be written anymore
+    pub blocked: usize,
+    pub state: BroadcastState,
+}
+
+impl BroadcastProgress {
+    fn to_checkpoint(&self, fingerprint: u64) -> String {
+        format!(
+            "{} {} {} {} {} {fingerprint}",
+            self.total, self.next, self.sent, self.failed, self.blocked
+        )
+    }
+
+    fn from_checkpoint(checkpoint: &str, total: usize, fingerprint: u64) -> Result<Self> {
+        let numbers = checkpoint
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<std::result::Result<Vec<u64>, _>>()?;
+        match numbers[..] {
+            [saved_total, next, sent, failed, blocked, saved_fingerprint]
+                if saved_total == total as u64 && saved_fingerprint == fingerprint =>
+            {
+                Ok(Self {
+                    total,
+                    next: next as usize,
+                    sent: sent as usize,
+                    failed: failed as usize,
+                    blocked: blocked as usize,
+                    state: BroadcastState::Running,
+                })
+            }
+            [_, _, _, _, _, _] => Err(anyhow!(
+                "checkpoint belongs to a broadcast to other chats or of another message"
+            )),
+            _ => Err(anyhow!("malformed broadcast checkpoint")),
+        }
+    }
+}
+
+/// FNV-1a of the chats and the message key, stable across builds unlike `DefaultHasher`
+fn fingerprint(chats: &[PackedChat], message_key: Option<&str>) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    let message_key = message_key.map(str::as_bytes).unwrap_or_default();
+    for byte in chats
+        .iter()
+        .flat_map(PackedChat::to_bytes)
+        .chain(message_key.iter().copied())
+    {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Follow and control a running broadcast
+pub struct BroadcastHandle {
+    progress: watch::Receiver<BroadcastProgress>,
+    control: watch::Sender<BroadcastState>,
+    task: JoinHandle<Result<BroadcastProgress>>,
+}
+
+impl BroadcastHandle {
+    pub fn progress(&self) -> BroadcastProgress {
+        self.progress.borrow().clone()
+    }
+
+    /// Wait for the next progress change
+    pub async fn changed(&mut self) -> Option<BroadcastProgress> {
+        self.progress.changed().await.ok()?;
+        Some(self.progress.borrow_and_update().clone())
+    }
+
+    /// Stop after the current send, until `resume`
+    pub fn pause(&self) {
+        self.control.send_replace(BroadcastState::Paused);
+    }
+
+    pub fn resume(&self) {
+        self.control.send_replace(BroadcastState::Running);
+    }
+
+    /// Stop after the current send, the checkpoint is kept to continue later
+    pub fn cancel(&self) {
+        self.control.send_replace(BroadcastState::Cancelled);
+    }
+
+    /// Wait until the broadcast finished or was cancelled
+    pub async fn wait(self) -> Result<BroadcastProgress> {
+        self.task.await?
+    }
+}
+
+pub(crate) fn spawn_broadcast<M>(
+    handle: ClientHandle,
+    chats: Vec<PackedChat>,
+    message: M,
+    options: BroadcastOptions,
+) -> BroadcastHandle
+where
+    M: Into<InputMessage> + Clone + Send + Sync + 'static,
+{
+    let initial = BroadcastProgress {
+        total: chats.len(),
+        next: 0,
+        sent: 0,
+        failed: 0,
+        blocked: 0,
+        state: BroadcastState::Running,
+    };
+    let (progress_tx, progress) = watch::channel(initial.clone());
+    let (control, control_rx) = watch::channel(BroadcastState::Running);
+    let task = tokio::spawn(async move {
+        let result = run_broadcast(
+            handle,
+            chats,
+            message,
+            options,
+            initial,
+            &progress_tx,
+            control_rx,
+        )
+        .await;
+        if let Err(err) = &result {
+            tracing::error!("broadcast error : {err:?}");
+        }
+        result
+    });
+    BroadcastHandle {
+        progress,
+        control,
+        task,
+    }
+}
+
+async fn run_broadcast<M>(
+    handle: ClientHandle,
+    chats: Vec<PackedChat>,
+    message: M,
+    options: BroadcastOptions,
+    mut progress: BroadcastProgress,
+    progress_tx: &watch::Sender<BroadcastProgress>,
+    mut control: watch::Receiver<BroadcastState>,
+) -> Result<BroadcastProgress>
+where
+    M: Into<InputMessage> + Clone + Send + Sync + 'static,
+{
+    let fingerprint = fingerprint(&chats, options.message_key.as_deref());
+    if let Some(checkpoint) = &options.checkpoint {
+        if let Some(saved) = read_checkpoint(checkpoint).await? {
+            progress = BroadcastProgress::from_checkpoint(&saved, chats.len(), fingerprint)?;
+            tracing::info!("broadcast resumed at {}/{}", progress.next, progress.total);
+        }
+    }
+    progress_tx.send_replace(progress.clone());
+    let mut in_batch = 0;
+    while progress.next < chats.len() {
+        if *control.borrow() == BroadcastState::Paused {
+            progress.state = BroadcastState::Paused;
+            save_checkpoint(&options, &progress, fingerprint).await?;
+            progress_tx.send_replace(progress.clone());
+        }
+        // a dropped `BroadcastHandle` can't resume or cancel, so keep running
+        let state = control
+            .wait_for(|state| *state != BroadcastState::Paused)
+            .await
+            .map(|state| *state)
+            .unwrap_or(BroadcastState::Running);
+        progress.state = BroadcastState::Running;
+        if state == BroadcastState::Cancelled {
+            progress.state = BroadcastState::Cancelled;
+            save_checkpoint(&options, &progress, fingerprint).await?;
+            progress_tx.send_replace(progress.clone());
+            return Ok(progress);
+        }
+        if in_batch >= options.batch_size.max(1) {
+            in_batch = 0;
+            // once per batch, syncing the file after every send is slow
+            save_checkpoint(&options, &progress, fingerprint).await?;
+            sleep(options.batch_delay).await;
+        }
+        let chat = chats[progress.next];
+        match handle
+            .send_message_with_priority(chat, message.clone(), SendPriority::Low)
+            .await
+        {
+            Ok(_) => progress.sent += 1,
+            Err(err) if is_blocked(&err) => progress.blocked += 1,
+            Err(err) => {
+                if handle.is_closed() {
+                    save_checkpoint(&options, &progress, fingerprint).await?;
+                    return Err(err);
+                }
+                tracing::warn!("broadcast to {} failed : {err}", chat.id);
+                progress.failed += 1;
+            }
+        }
+        in_batch += 1;
+        progress.next += 1;
+        progress_tx.send_replace(progress.clone());
+    }
+    if let Some(checkpoint) = &options.checkpoint {
+        let checkpoint = checkpoint.clone();
+        tokio::task::spawn_blocking(move || std::fs::remove_file(checkpoint)).await??;
+    }
+    progress.state = BroadcastState::Finished;
+    progress_tx.send_replace(progress.clone());
+    Ok(progress)
+}
+
+fn is_blocked(err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<InvocationError>() {
+        Some(err) => {
+            err.is("USER_IS_BLOCKED")
+                || err.is("USER_DEACTIVATED*")
+                || err.is("INPUT_USER_DEACTIVATED")
+                || err.is("PEER_ID_INVALID")
+                || err.is("CHAT_WRITE_FORBIDDEN")
+                || err.is("CHANNEL_PRIVATE")
+                || err.is("USER_BANNED_IN_CHANNEL")
+        }
+        None => false,
+    }
+}
+
+async fn read_checkpoint(path: &Path) -> Result<Option<String>> {
+    let path = path.to_path_buf();
+    Ok(
+        tokio::task::spawn_blocking(move || match std::fs::read_to_string(path) {
+            Ok(content) => Ok(Some(content)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        })
+        .await??,
+    )
+}
+
+async fn save_checkpoint(
+    options: &BroadcastOptions,
+    progress: &BroadcastProgress,
+    fingerprint: u64,
+) -> Result<()> {
+    let path = match &options.checkpoint {
+        Some(path) => path.clone(),
+        None => return Ok(()),
+    };
+    let content = progress.to_checkpoint(fingerprint);
+    tokio::task::spawn_blocking(move || write_atomic(&path, content.as_bytes())).await?
+}
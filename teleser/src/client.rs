@@ -1,28 +1,57 @@
 use crate::Result;
+use std::cell::Cell;
 use std::cmp::min;
 
+use crate::auth::Auth;
+use crate::broadcast::{BroadcastHandle, BroadcastOptions};
+use crate::handle::{ClientHandle, HandleState};
 use crate::handler::Module;
-use anyhow::anyhow;
-use async_trait::async_trait;
-use grammers_client::{Config, InitParams, SignInError, Update};
-use grammers_session::Session;
+use crate::limit::{RateLimit, RateLimiter};
+use crate::outbound::OutboundQueue;
+use crate::retry::{ErrorHandler, Retry, RetryPolicy};
+use crate::session::SessionStore;
+use grammers_client::types::ChatMap;
+use grammers_client::{Config, InitParams, InputMessage, Update};
+use grammers_session::{PackedChat, Session};
 use grammers_tl_types as tl;
+use grammers_tl_types::{Deserializable, Serializable};
+use std::collections::HashMap;
+use std::future::Future;
 use std::ops::Deref;
-use std::path::Path;
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{watch, Mutex};
 use tokio::task;
-use tokio::time::sleep;
+use tokio::time::{sleep, Instant, Interval, MissedTickBehavior};
 
 pub struct Client {
     pub inner_client: Mutex<Option<grammers_client::Client>>,
     pub modules: Arc<Vec<Module>>,
-    api_id: i32,
-    api_hash: String,
-    auth: Auth,
+    pub(crate) api_id: i32,
+    pub(crate) api_hash: String,
+    pub(crate) auth: Auth,
     session_store: Box<dyn SessionStore + Sync + Send>,
     init_params: Option<InitParams>,
+    handle_state: watch::Sender<HandleState>,
+    retry: Arc<Retry>,
+    limiter: Option<Arc<RateLimiter>>,
+    outbound: Option<Arc<OutboundQueue>>,
+    shutdown: Arc<watch::Sender<Option<ClientExit>>>,
+    catch_up_window: Option<Duration>,
+    session_save_interval: Option<Duration>,
+    saved_session: Mutex<Option<Vec<u8>>>,
+    relogin: bool,
+    auto_answer_callback: bool,
+}
+
+/// Why `run_client_and_reconnect` returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientExit {
+    /// Stopped by ctrl-c or `Client::shutdown`
+    Shutdown,
+    /// Logged out by `log_out` of the `Client` or a `ClientHandle`,
+    /// or the session was revoked and re-login is disabled
+    LoggedOut,
 }
 
 enum MapResult<'a> {
@@ -67,7 +96,46 @@ macro_rules! map_modules {
     }};
 }
 
-async fn hand(modules: Arc<Vec<Module>>, mut client: grammers_client::Client, update: Update) {
+async fn hand(
+    modules: Arc<Vec<Module>>,
+    handle: ClientHandle,
+    client: grammers_client::Client,
+    update: Update,
+    auto_answer_callback: bool,
+) {
+    let callback_query_id = match &update {
+        Update::CallbackQuery(query) if auto_answer_callback => Some(query.raw.query_id),
+        _ => None,
+    };
+    let answered = crate::handle::CURRENT_HANDLE
+        .scope(
+            handle.clone(),
+            crate::traits::CALLBACK_ANSWERED.scope(Cell::new(false), async {
+                hand_update(modules, client, update).await;
+                crate::traits::CALLBACK_ANSWERED.with(Cell::get)
+            }),
+        )
+        .await;
+    // stop the spinner of the button if no handler answered
+    if let Some(query_id) = callback_query_id.filter(|_| !answered) {
+        let answer = tl::functions::messages::SetBotCallbackAnswer {
+            alert: false,
+            query_id,
+            message: None,
+            url: None,
+            cache_time: 0,
+        };
+        if let Err(err) = handle.invoke(&answer).await {
+            tracing::debug!("auto answer callback query error : {err}");
+        }
+    }
+}
+
+async fn hand_update(
+    modules: Arc<Vec<Module>>,
+    mut client: grammers_client::Client,
+    update: Update,
+) {
     let client_point = &mut client;
     let update_point = &update;
     match update_point {
@@ -148,13 +216,14 @@ async fn hand(modules: Arc<Vec<Module>>, mut client: grammers_client::Client, up
 
 impl Client {
     async fn load_session(&self) -> Result<Session> {
-        Ok(
-            if let Some(data) = self.session_store.on_load_session().await? {
-                Session::load(&data)?
-            } else {
-                Session::new()
-            },
-        )
+        let data = self.session_store.on_load_session().await?;
+        let session = if let Some(data) = &data {
+            Session::load(data)?
+        } else {
+            Session::new()
+        };
+        *self.saved_session.lock().await = data;
+        Ok(session)
     }
 
     async fn set_client(&self, inner_client: Option<grammers_client::Client>) {
@@ -163,25 +232,393 @@ impl Client {
         drop(lock);
     }
 
-    async fn connect(&self) -> Result<grammers_client::Client> {
+    pub(crate) async fn connect(&self) -> Result<grammers_client::Client> {
+        let session = self.load_session().await?;
+        self.connect_with_session(session).await
+    }
+
+    /// Connect to the data center of the session user, without a user to the default one
+    pub(crate) async fn connect_with_session(
+        &self,
+        session: Session,
+    ) -> Result<grammers_client::Client> {
+        let mut params = self.init_params.clone().unwrap_or_default();
+        if let Some(window) = self.catch_up_window {
+            params.catch_up = update_state_in_window(&session, window);
+        }
         let connect = grammers_client::Client::connect(Config {
-            session: self.load_session().await?,
-            api_id: self.api_id.clone(), // not actually logging in, but has to look real
+            session,
+            api_id: self.api_id, // not actually logging in, but has to look real
             api_hash: self.api_hash.clone(),
-            params: match self.init_params.clone() {
-                None => InitParams::default(),
-                Some(params) => params,
-            },
+            params,
         })
         .await;
         let client = connect?;
         self.set_client(Some(client.clone())).await;
         Ok(client)
     }
+
+    /// Fetch the updates missed while offline with `updates.getDifference`,
+    /// then `updates.getChannelDifference` for the stored channels the difference
+    /// names (it only returns the access hashes of those).
+    /// grammers only gets the difference once a gap shows up or nothing was received
+    /// for 15 minutes, so connect again with the state after the difference
+    /// and return the missed updates.
+    async fn catch_up(
+        &self,
+        inner_client: &grammers_client::Client,
+    ) -> Result<Option<(grammers_client::Client, Vec<Update>)>> {
+        let session = inner_client.session();
+        let window = match self.catch_up_window {
+            Some(window) => window,
+            None => return Ok(None),
+        };
+        if !update_state_in_window(session, window) {
+            return Ok(None);
+        }
+        let mut state = match session.get_state() {
+            Some(state) => state,
+            None => return Ok(None),
+        };
+        let mut missed = Vec::new();
+        let mut access_hashes = HashMap::new();
+        loop {
+            let difference = inner_client
+                .invoke(&tl::functions::updates::GetDifference {
+                    pts: state.pts,
+                    pts_limit: None,
+                    pts_total_limit: None,
+                    date: state.date,
+                    qts: state.qts,
+                    qts_limit: None,
+                })
+                .await?;
+            let (new_messages, other_updates, users, chats, next, more) = match difference {
+                tl::enums::updates::Difference::Empty(empty) => {
+                    state.date = empty.date;
+                    state.seq = empty.seq;
+                    break;
+                }
+                tl::enums::updates::Difference::TooLong(too_long) => {
+                    tracing::warn!("too many missed updates, skip the rest");
+                    state.pts = too_long.pts;
+                    break;
+                }
+                tl::enums::updates::Difference::Difference(d) => (
+                    d.new_messages,
+                    d.other_updates,
+                    d.users,
+                    d.chats,
+                    d.state,
+                    false,
+                ),
+                tl::enums::updates::Difference::Slice(d) => (
+                    d.new_messages,
+                    d.other_updates,
+                    d.users,
+                    d.chats,
+                    d.intermediate_state,
+                    true,
+                ),
+            };
+            access_hashes.extend(chats.iter().filter_map(channel_access_hash));
+            let new_messages = new_messages.into_iter().map(|message| {
+                tl::types::UpdateNewMessage {
+                    message,
+                    pts: 0,
+                    pts_count: 0,
+                }
+                .into()
+            });
+            let chats = ChatMap::new(users, chats);
+            missed.extend(
+                new_messages
+                    .chain(other_updates)
+                    .filter_map(|update| Update::new(inner_client, update, &chats)),
+            );
+            let tl::enums::updates::State::State(next) = next;
+            state.pts = next.pts;
+            state.qts = next.qts;
+            state.date = next.date;
+            state.seq = next.seq;
+            if !more {
+                break;
+            }
+        }
+        let bot = session.get_user().is_some_and(|user| user.bot);
+        for channel in &mut state.channels {
+            // grammers_session does not export the type, read it as constructor, id and pts
+            let mut bytes = channel.to_bytes();
+            let channel_id = i64::from_le_bytes(bytes[4..12].try_into()?);
+            let mut pts = i32::from_le_bytes(bytes[12..16].try_into()?);
+            let access_hash = match access_hashes.get(&channel_id) {
+                Some(access_hash) => *access_hash,
+                None => continue,
+            };
+            let input_channel = tl::types::InputChannel {
+                channel_id,
+                access_hash,
+            };
+            if let Err(e) =
+                catch_up_channel(inner_client, input_channel, bot, &mut pts, &mut missed).await
+            {
+                tracing::warn!("catch up channel {channel_id} error : {e}");
+            }
+            bytes[12..16].copy_from_slice(&pts.to_le_bytes());
+            *channel = Deserializable::from_bytes(&bytes)?;
+        }
+        tracing::info!("caught up {} missed updates", missed.len());
+        session.set_state(state);
+        // the message box of grammers only loads the update state on connect
+        let inner_client = self
+            .connect_with_session(Session::load(&session.save())?)
+            .await?;
+        Ok(Some((inner_client, missed)))
+    }
+
+    /// Save the session with the latest update state through the `SessionStore`,
+    /// skip writing if nothing changed since the last load or save
+    pub(crate) async fn save_session(&self, inner_client: &grammers_client::Client) -> Result<()> {
+        inner_client.sync_update_state();
+        let data = inner_client.session().save();
+        let mut saved = self.saved_session.lock().await;
+        if saved.as_ref() == Some(&data) {
+            return Ok(());
+        }
+        self.session_store.on_save_session(data.clone()).await?;
+        *saved = Some(data);
+        tracing::debug!("session saved");
+        Ok(())
+    }
+
+    /// Remove the stored session through the `SessionStore`
+    pub(crate) async fn clear_session(&self) -> Result<()> {
+        let mut saved = self.saved_session.lock().await;
+        self.session_store.on_clear_session().await?;
+        *saved = None;
+        tracing::debug!("session cleared");
+        Ok(())
+    }
+
+    fn set_handle_state(&self, state: HandleState) {
+        self.handle_state.send_replace(state);
+    }
+
+    /// A cloneable handle waiting for the connected and authorized inner client
+    pub fn handle(&self) -> ClientHandle {
+        ClientHandle::new(
+            self.handle_state.subscribe(),
+            self.retry.clone(),
+            self.limiter.clone(),
+            self.outbound.clone(),
+            self.shutdown.clone(),
+        )
+    }
+
+    /// Send the message to every chat in the background, see `ClientHandle::broadcast`
+    pub fn broadcast<M>(
+        &self,
+        chats: Vec<PackedChat>,
+        message: M,
+        options: BroadcastOptions,
+    ) -> BroadcastHandle
+    where
+        M: Into<InputMessage> + Clone + Send + Sync + 'static,
+    {
+        self.handle().broadcast(chats, message, options)
+    }
+
+    /// Name of the account, used to tell clients apart in a `ClientPool`
+    pub fn account(&self) -> &str {
+        &self.retry.account
+    }
+
+    /// Ask `run_client_and_reconnect` to stop, like ctrl-c but for this client only,
+    /// also while it connects, logs in or waits to reconnect. If the client is not running yet, the next run stops right away.
+    pub fn shutdown(&self) {
+        self.shutdown.send_replace(Some(ClientExit::Shutdown));
+    }
+
+    /// Log out the account, see `ClientHandle::log_out`
+    pub async fn log_out(&self) -> Result<()> {
+        self.handle().log_out().await
+    }
+
+    /// The active sessions of the account, see `ClientHandle::authorizations`
+    pub async fn authorizations(&self) -> Result<Vec<tl::types::Authorization>> {
+        self.handle().authorizations().await
+    }
+
+    /// Terminate another session by the `hash` of its authorization
+    pub async fn terminate_authorization(&self, hash: i64) -> Result<()> {
+        self.handle().terminate_authorization(hash).await
+    }
+
+    /// Terminate every session of the account except this one
+    pub async fn terminate_other_authorizations(&self) -> Result<()> {
+        self.handle().terminate_other_authorizations().await
+    }
+}
+
+fn update_state_in_window(session: &Session, window: Duration) -> bool {
+    let state = match session.get_state() {
+        Some(state) => state,
+        None => return false,
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    let offline = now - state.date as i64;
+    if offline > window.as_secs() as i64 {
+        tracing::warn!("update state is {offline} sec old, skip catch up");
+        false
+    } else {
+        tracing::debug!("update state is {offline} sec old");
+        true
+    }
+}
+
+/// The access hash of a channel, `min` channels have none usable
+fn channel_access_hash(chat: &tl::enums::Chat) -> Option<(i64, i64)> {
+    match chat {
+        tl::enums::Chat::Channel(channel) if !channel.min => channel
+            .access_hash
+            .map(|access_hash| (channel.id, access_hash)),
+        _ => None,
+    }
 }
 
-pub async fn run_client_and_reconnect<S: Into<Arc<Client>>>(client: S) -> Result<()> {
+/// Fetch the updates of one channel since `pts`, like grammers does on a gap
+async fn catch_up_channel(
+    inner_client: &grammers_client::Client,
+    channel: tl::types::InputChannel,
+    bot: bool,
+    pts: &mut i32,
+    missed: &mut Vec<Update>,
+) -> Result<()> {
+    loop {
+        let difference = inner_client
+            .invoke(&tl::functions::updates::GetChannelDifference {
+                force: false,
+                channel: channel.clone().into(),
+                filter: tl::enums::ChannelMessagesFilter::Empty,
+                pts: *pts,
+                limit: if bot { 100000 } else { 100 },
+            })
+            .await?;
+        let d = match difference {
+            tl::enums::updates::ChannelDifference::Empty(empty) => {
+                *pts = empty.pts;
+                return Ok(());
+            }
+            tl::enums::updates::ChannelDifference::TooLong(too_long) => {
+                tracing::warn!("too many missed updates in a channel, skip the rest");
+                if let tl::enums::Dialog::Dialog(dialog) = too_long.dialog {
+                    *pts = dialog.pts.unwrap_or(*pts);
+                }
+                return Ok(());
+            }
+            tl::enums::updates::ChannelDifference::Difference(d) => d,
+        };
+        let new_messages = d.new_messages.into_iter().map(|message| {
+            tl::types::UpdateNewChannelMessage {
+                message,
+                pts: 0,
+                pts_count: 0,
+            }
+            .into()
+        });
+        let chats = ChatMap::new(d.users, d.chats);
+        missed.extend(
+            new_messages
+                .chain(d.other_updates)
+                .filter_map(|update| Update::new(inner_client, update, &chats)),
+        );
+        *pts = d.pts;
+        if d.r#final {
+            return Ok(());
+        }
+    }
+}
+
+/// Catch up with the stored update state, keep the client if it fails
+async fn catch_up(client: &Client, inner_client: &mut grammers_client::Client) -> Vec<Update> {
+    match client.catch_up(inner_client).await {
+        Ok(Some((client_new, missed))) => {
+            *inner_client = client_new;
+            missed
+        }
+        Ok(None) => Vec::new(),
+        Err(e) => {
+            tracing::error!("catch up error : {e}");
+            Vec::new()
+        }
+    }
+}
+
+fn dispatch(client: &Client, inner_client: &grammers_client::Client, update: Update) {
+    task::spawn(hand(
+        client.modules.clone(),
+        client.handle(),
+        inner_client.clone(),
+        update,
+        client.auto_answer_callback,
+    ));
+}
+
+async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// The session is not authorized anymore, clear it and login again if enabled
+async fn logged_out(client: &Client) -> Result<Option<grammers_client::Client>> {
+    tracing::error!("logged out");
+    client.clear_session().await?;
+    if !client.relogin {
+        return Ok(None);
+    }
+    let inner_client = client.connect().await?;
+    let (inner_client, usr) = crate::auth::login(client, inner_client).await?;
+    tracing::info!("login again with id : {}", usr.id());
+    client.save_session(&inner_client).await?;
+    Ok(Some(inner_client))
+}
+
+pub async fn run_client_and_reconnect<S: Into<Arc<Client>>>(client: S) -> Result<ClientExit> {
     let client = client.into();
+    // a shutdown asked before the run started stops it right away
+    let shutdown = client.shutdown.subscribe();
+    if let Some(exit) = client.shutdown.send_replace(None) {
+        client.set_handle_state(HandleState::Closed);
+        return Ok(exit);
+    }
+    client.set_handle_state(HandleState::Pending);
+    // sends once the client is ready
+    let drain = client.outbound.clone().map(|outbound| {
+        let handle = client.handle();
+        task::spawn(async move { outbound.drain(handle).await })
+    });
+    let scheduler = task::spawn(crate::schedule::run_scheduler(
+        client.modules.clone(),
+        client.handle(),
+    ));
+    let result = run_client(&client, shutdown).await;
+    client.set_handle_state(HandleState::Closed);
+    if let Some(drain) = drain {
+        drain.abort();
+    }
+    scheduler.abort();
+    result
+}
+
+/// Connect and login, or resume the stored session
+async fn start_client(client: &Client) -> Result<(grammers_client::Client, Vec<Update>)> {
     let mut inner_client = client.connect().await?;
     tracing::info!("Connected! (first)");
     tracing::info!("Sending ping...");
@@ -191,94 +628,188 @@ pub async fn run_client_and_reconnect<S: Into<Arc<Client>>>(client: S) -> Result
             .invoke(&tl::functions::Ping { ping_id: 0 })
             .await?
     );
+    let mut missed = Vec::new();
     if !inner_client.is_authorized().await? {
-        let usr = match &client.auth {
-            Auth::AuthWithPhoneAndCode(auth) => {
-                let token = inner_client
-                    .request_login_code(auth.input_phone().await?.as_str())
-                    .await?;
-                match inner_client
-                    .sign_in(&token, auth.input_code().await?.as_str())
-                    .await
-                {
-                    Err(SignInError::PasswordRequired(password_token)) => {
-                        inner_client
-                            .check_password(password_token, auth.input_password().await?.as_str())
-                            .await?
+        let (new_client, usr) = crate::auth::login(client, inner_client).await?;
+        inner_client = new_client;
+        tracing::info!("login with id : {}", usr.id());
+        client.save_session(&inner_client).await?;
+    } else {
+        let usr = inner_client.get_me().await?;
+        tracing::info!("session with id : {}", usr.id());
+        // imported sessions may only know the data center of the user
+        if let Some(user) = inner_client.session().get_user() {
+            if user.id != usr.id() {
+                inner_client
+                    .session()
+                    .set_user(usr.id(), user.dc, usr.is_bot());
+            }
+        }
+        missed = catch_up(client, &mut inner_client).await;
+        // auth keys of new data centers or a migrated user
+        client.save_session(&inner_client).await?;
+    }
+    Ok((inner_client, missed))
+}
+
+/// Connect again after an error, sleeping before the next try if it fails.
+/// Returns the exit if the session was revoked and re-login is disabled.
+async fn reconnect(
+    client: &Client,
+    inner_client: &mut grammers_client::Client,
+    error_counter: &mut u32,
+) -> Result<Option<ClientExit>> {
+    let error = match client.connect().await {
+        Ok(client_new) => {
+            tracing::warn!("reconnected");
+            *inner_client = client_new;
+            match inner_client.is_authorized().await {
+                Ok(auth) => {
+                    let mut missed = Vec::new();
+                    if !auth {
+                        match logged_out(client).await? {
+                            Some(client_new) => *inner_client = client_new,
+                            None => return Ok(Some(ClientExit::LoggedOut)),
+                        }
+                    } else {
+                        missed = catch_up(client, inner_client).await;
+                    }
+                    // reconnected, the next tick must not reconnect again
+                    *error_counter = 0;
+                    client.set_handle_state(HandleState::Ready(inner_client.clone()));
+                    if let Err(e) = client.save_session(inner_client).await {
+                        tracing::error!("save session error : {e}");
+                    }
+                    for update in missed {
+                        dispatch(client, inner_client, update);
                     }
-                    Ok(usr) => usr,
-                    Err(err) => return Err(anyhow!(err)),
+                    return Ok(None);
                 }
+                Err(e) => format!("reconnect auth error : {e}"),
             }
-            Auth::AuthWithBotToken(auth) => {
-                inner_client
-                    .bot_sign_in(auth.input_bot_token().await?.as_str())
-                    .await?
+        }
+        Err(e) => format!("reconnect error : {e}"),
+    };
+    *error_counter += 1;
+    let sleep_sec = 2_u64.pow(min(10, *error_counter));
+    tracing::error!("{error} : sleep {sleep_sec} sec");
+    sleep(Duration::from_secs(sleep_sec)).await;
+    Ok(None)
+}
+
+/// Wait for ctrl-c or a shutdown asked by `Client::shutdown` or `log_out`
+async fn shutdown_asked(
+    client: &Client,
+    shutdown: &mut watch::Receiver<Option<ClientExit>>,
+) -> ClientExit {
+    let asked = async {
+        loop {
+            // the client owns the sender, it is not dropped while running
+            let _ = shutdown.changed().await;
+            // take the signal, so the next run does not stop on it
+            if let Some(exit) = client.shutdown.send_replace(None) {
+                return exit;
             }
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => ClientExit::Shutdown,
+        exit = asked => exit,
+    }
+}
+
+/// Await `future` unless a shutdown is asked first, then return the exit as the error
+async fn until_shutdown<T>(
+    client: &Client,
+    shutdown: &mut watch::Receiver<Option<ClientExit>>,
+    future: impl Future<Output = T>,
+) -> std::result::Result<T, ClientExit> {
+    tokio::select! {
+        value = future => Ok(value),
+        exit = shutdown_asked(client, shutdown) => Err(exit),
+    }
+}
+
+/// Clear the session after `log_out`
+async fn stopped(client: &Client, exit: ClientExit) -> Result<ClientExit> {
+    if exit == ClientExit::LoggedOut {
+        client.set_handle_state(HandleState::Pending);
+        client.clear_session().await?;
+    }
+    Ok(exit)
+}
+
+async fn run_client(
+    client: &Arc<Client>,
+    mut shutdown: watch::Receiver<Option<ClientExit>>,
+) -> Result<ClientExit> {
+    // connecting and logging in may wait long, like for a login code
+    let (mut inner_client, missed) =
+        match until_shutdown(client, &mut shutdown, start_client(client)).await {
+            Ok(started) => started?,
+            Err(exit) => return stopped(client, exit).await,
         };
-        tracing::info!("login with id : {}", usr.id());
-        client
-            .session_store
-            .on_save_session(inner_client.session().save())
-            .await?;
-    } else {
-        let usr = inner_client.get_me().await?;
-        tracing::info!("session with id : {}", usr.id());
+    client.set_handle_state(HandleState::Ready(inner_client.clone()));
+    for update in missed {
+        dispatch(client, &inner_client, update);
     }
 
     let mut error_counter = 0;
+    let mut save_interval = client.session_save_interval.map(|period| {
+        let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        interval
+    });
 
     tracing::info!("Waiting for messages...");
 
     // loop
-    loop {
+    let exit = loop {
         // reconnect
         if error_counter > 0 {
-            match client.connect().await {
-                Ok(client_new) => {
-                    tracing::warn!("reconnected");
-                    inner_client = client_new;
-                    match inner_client.is_authorized().await {
-                        Ok(auth) => {
-                            if !auth {
-                                tracing::error!("logged out, exit");
-                                break;
-                            }
-                        }
-                        Err(e) => {
-                            error_counter += 1;
-                            let sleep_sec = 2_u64.pow(min(10, error_counter));
-                            tracing::error!("reconnect auth error : sleep {sleep_sec} sec : {e}");
-                            sleep(Duration::from_secs(sleep_sec)).await;
-                        }
+            let reconnect = reconnect(client, &mut inner_client, &mut error_counter);
+            match until_shutdown(client, &mut shutdown, reconnect).await {
+                Ok(result) => {
+                    if let Some(exit) = result? {
+                        break exit;
                     }
                 }
-                Err(e) => {
-                    error_counter += 1;
-                    let sleep_sec = 2_u64.pow(min(10, error_counter));
-                    tracing::error!("reconnect error : sleep {sleep_sec} sec : {e}");
-                    sleep(Duration::from_secs(sleep_sec)).await;
-                }
+                Err(exit) => break stopped(client, exit).await?,
             }
         }
         tokio::select! {
             result = inner_client.next_update() => match result {
                 Ok(update)=> {
                     error_counter = 0;
-                    task::spawn(hand(client.modules.clone(),inner_client.clone(), update));
+                    dispatch(client, &inner_client, update);
                 }
                 Err(e)=>{
+                    client.set_handle_state(HandleState::Pending);
+                    if let Err(e) = client.save_session(&inner_client).await {
+                        tracing::error!("save session error : {e}");
+                    }
                     error_counter+=1;
                     let sleep_sec = 2_u64.pow(min(10,error_counter));
                     tracing::error!("next_update error : sleep {sleep_sec} sec : {e}");
-                    sleep(Duration::from_secs(sleep_sec)).await;
+                    let backoff = sleep(Duration::from_secs(sleep_sec));
+                    if let Err(exit) = until_shutdown(client, &mut shutdown, backoff).await {
+                        break stopped(client, exit).await?;
+                    }
                 }
             },
-            _ = tokio::signal::ctrl_c() => break,
+            _ = tick(&mut save_interval) => {
+                if let Err(e) = client.save_session(&inner_client).await {
+                    tracing::error!("save session error : {e}");
+                }
+            },
+            exit = shutdown_asked(client, &mut shutdown) => break stopped(client, exit).await?,
         }
-    }
+    };
 
-    Ok(())
+    if exit == ClientExit::Shutdown {
+        client.save_session(&inner_client).await?;
+    }
+    Ok(exit)
 }
 
 pub struct ClientBuilder {
@@ -288,6 +819,21 @@ pub struct ClientBuilder {
     session_store: Option<Box<dyn SessionStore + Sync + Send>>,
     modules: Option<Arc<Vec<Module>>>,
     init_params: Option<InitParams>,
+    account: Option<String>,
+    retry_policy: RetryPolicy,
+    error_handler: Option<ErrorHandler>,
+    rate_limit: Option<RateLimit>,
+    outbound: Option<Arc<OutboundQueue>>,
+    catch_up_window: Option<Duration>,
+    session_save_interval: Option<Duration>,
+    relogin: bool,
+    auto_answer_callback: bool,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ClientBuilder {
@@ -299,6 +845,15 @@ impl ClientBuilder {
             session_store: None,
             modules: None,
             init_params: None,
+            account: None,
+            retry_policy: RetryPolicy::default(),
+            error_handler: None,
+            rate_limit: None,
+            outbound: None,
+            catch_up_window: None,
+            session_save_interval: None,
+            relogin: false,
+            auto_answer_callback: false,
         }
     }
 
@@ -359,69 +914,124 @@ impl ClientBuilder {
         self
     }
 
-    pub fn build(self) -> Result<Client> {
-        return Ok(Client {
-            modules: self.modules.expect("must set modules"),
-            inner_client: Mutex::new(None),
-            api_id: self.api_id.expect("must set api_id"),
-            api_hash: self.api_hash.expect("must set api_hash"),
-            auth: self.auth.expect("must set auth"),
-            session_store: self.session_store.expect("must set session_store"),
-            init_params: self.init_params,
-        });
+    pub fn set_account<S: Into<String>>(&mut self, account: S) {
+        self.account = Some(account.into())
     }
-}
 
-pub enum Auth {
-    AuthWithBotToken(Box<dyn AuthWithBotToken + Send + Sync>),
-    AuthWithPhoneAndCode(Box<dyn AuthWithPhoneAndCode + Send + Sync>),
-}
+    pub fn with_account<S: Into<String>>(mut self, account: S) -> Self {
+        self.set_account(account);
+        self
+    }
 
-#[async_trait]
-pub trait AuthWithBotToken {
-    async fn input_bot_token(&self) -> Result<String>;
-}
+    /// How the helpers of `ClientHandle` retry flood waits and transient errors
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy
+    }
 
-#[async_trait]
-pub trait AuthWithPhoneAndCode {
-    async fn input_phone(&self) -> Result<String>;
-    async fn input_code(&self) -> Result<String>;
-    async fn input_password(&self) -> Result<String>;
-}
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.set_retry_policy(retry_policy);
+        self
+    }
 
-#[async_trait]
-pub trait SessionStore {
-    async fn on_save_session(&self, data: Vec<u8>) -> Result<()>;
-    async fn on_load_session(&self) -> Result<Option<Vec<u8>>>;
-}
+    /// Called with the errors of the client, like long flood waits, instead of logging them
+    pub fn set_error_handler(&mut self, error_handler: ErrorHandler) {
+        self.error_handler = Some(error_handler)
+    }
 
-pub struct StaticBotToken {
-    pub token: String,
-}
+    pub fn with_error_handler(mut self, error_handler: ErrorHandler) -> Self {
+        self.set_error_handler(error_handler);
+        self
+    }
 
-#[async_trait]
-impl AuthWithBotToken for StaticBotToken {
-    async fn input_bot_token(&self) -> Result<String> {
-        return Ok(self.token.clone());
+    /// Queue the sends of `ClientHandle` under these limits, none by default
+    pub fn set_rate_limit(&mut self, rate_limit: Option<RateLimit>) {
+        self.rate_limit = rate_limit
     }
-}
 
-pub struct FileSessionStore {
-    pub path: String,
-}
+    pub fn with_rate_limit(mut self, rate_limit: Option<RateLimit>) -> Self {
+        self.set_rate_limit(rate_limit);
+        self
+    }
 
-#[async_trait]
-impl SessionStore for FileSessionStore {
-    async fn on_save_session(&self, data: Vec<u8>) -> Result<()> {
-        tokio::fs::write(self.path.as_str(), data).await?;
-        Ok(())
+    /// Send the messages of the queue once connected, keep the `Arc` to enqueue
+    pub fn set_outbound_queue(&mut self, outbound: Arc<OutboundQueue>) {
+        self.outbound = Some(outbound)
     }
-    async fn on_load_session(&self) -> Result<Option<Vec<u8>>> {
-        let path = Path::new(self.path.as_str());
-        if path.exists() {
-            Ok(Some(tokio::fs::read(path).await?))
-        } else {
-            Ok(None)
-        }
+
+    pub fn with_outbound_queue(mut self, outbound: Arc<OutboundQueue>) -> Self {
+        self.set_outbound_queue(outbound);
+        self
+    }
+
+    /// Fetch the updates missed while offline on connect, also in the channels
+    /// with missed updates, unless the stored update state is older than the window.
+    /// Catching up connects a second time, grammers only loads the update state on connect.
+    pub fn set_catch_up_window(&mut self, window: Option<Duration>) {
+        self.catch_up_window = window
+    }
+
+    pub fn with_catch_up_window(mut self, window: Option<Duration>) -> Self {
+        self.set_catch_up_window(window);
+        self
+    }
+
+    /// Save the session periodically besides on login, reconnect and exit
+    pub fn set_session_save_interval(&mut self, interval: Option<Duration>) {
+        self.session_save_interval = interval
+    }
+
+    pub fn with_session_save_interval(mut self, interval: Option<Duration>) -> Self {
+        self.set_session_save_interval(interval);
+        self
+    }
+
+    /// Login again with the `Auth` when the session is logged out or revoked,
+    /// instead of returning `ClientExit::LoggedOut`
+    pub fn set_relogin(&mut self, relogin: bool) {
+        self.relogin = relogin
+    }
+
+    pub fn with_relogin(mut self, relogin: bool) -> Self {
+        self.set_relogin(relogin);
+        self
+    }
+
+    /// Answer the callback queries no handler answered with the `CallbackQueryExt` helpers,
+    /// once the dispatch completes, so the button stops spinning
+    pub fn set_auto_answer_callback(&mut self, auto_answer_callback: bool) {
+        self.auto_answer_callback = auto_answer_callback
+    }
+
+    pub fn with_auto_answer_callback(mut self, auto_answer_callback: bool) -> Self {
+        self.set_auto_answer_callback(auto_answer_callback);
+        self
+    }
+
+    pub fn build(self) -> Result<Client> {
+        Ok(Client {
+            modules: self.modules.expect("must set modules"),
+            inner_client: Mutex::new(None),
+            api_id: self.api_id.expect("must set api_id"),
+            api_hash: self.api_hash.expect("must set api_hash"),
+            auth: self.auth.expect("must set auth"),
+            session_store: self.session_store.expect("must set session_store"),
+            init_params: self.init_params,
+            handle_state: watch::channel(HandleState::Pending).0,
+            retry: Arc::new(Retry {
+                account: self.account.unwrap_or_else(|| "default".to_owned()).into(),
+                policy: self.retry_policy,
+                error_handler: self.error_handler,
+            }),
+            limiter: self
+                .rate_limit
+                .map(|limit| Arc::new(RateLimiter::new(limit))),
+            outbound: self.outbound,
+            shutdown: Arc::new(watch::channel(None).0),
+            catch_up_window: self.catch_up_window,
+            session_save_interval: self.session_save_interval,
+            saved_session: Mutex::new(None),
+            relogin: self.relogin,
+            auto_answer_callback: self.auto_answer_callback,
+        })
     }
 }
@@ -0,0 +1,350 @@
+use crate::broadcast::{spawn_broadcast, BroadcastHandle, BroadcastOptions};
+use crate::limit::{RateLimiter, SendPriority};
+use crate::outbound::OutboundQueue;
+use crate::retry::Retry;
+use crate::split::{FormattedText, MAX_MESSAGE_LENGTH};
+use crate::{ClientExit, Result};
+use anyhow::anyhow;
+use grammers_client::types::{Message, User};
+use grammers_client::{InputMessage, InvocationError};
+use grammers_session::PackedChat;
+use grammers_tl_types as tl;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::time::sleep;
+
+#[derive(Clone)]
+pub(crate) enum HandleState {
+    Pending,
+    Ready(grammers_client::Client),
+    Closed,
+}
+
+tokio::task_local! {
+    pub(crate) static CURRENT_HANDLE: ClientHandle;
+}
+
+/// A cloneable handle to the connected client, usable from background tasks.
+///
+/// Every method waits until the client is connected and authorized,
+/// and always uses the latest inner client after a reconnect.
+/// Requests are retried on flood waits and transient errors by the `RetryPolicy`.
+#[derive(Clone)]
+pub struct ClientHandle {
+    state: watch::Receiver<HandleState>,
+    retry: Arc<Retry>,
+    limiter: Option<Arc<RateLimiter>>,
+    outbound: Option<Arc<OutboundQueue>>,
+    shutdown: Arc<watch::Sender<Option<ClientExit>>>,
+}
+
+impl ClientHandle {
+    pub(crate) fn new(
+        state: watch::Receiver<HandleState>,
+        retry: Arc<Retry>,
+        limiter: Option<Arc<RateLimiter>>,
+        outbound: Option<Arc<OutboundQueue>>,
+        shutdown: Arc<watch::Sender<Option<ClientExit>>>,
+    ) -> Self {
+        Self {
+            state,
+            retry,
+            limiter,
+            outbound,
+            shutdown,
+        }
+    }
+
+    /// The handle of the client dispatching the current update,
+    /// `None` outside of handlers.
+    pub fn current() -> Option<ClientHandle> {
+        CURRENT_HANDLE.try_with(|handle| handle.clone()).ok()
+    }
+
+    /// Name of the account this handle belongs to
+    pub fn account(&self) -> &str {
+        &self.retry.account
+    }
+
+    /// Wait for a connected and authorized inner client,
+    /// returns an error if the client has stopped.
+    pub async fn client(&self) -> Result<grammers_client::Client> {
+        let mut state = self.state.clone();
+        loop {
+            let current = state.borrow_and_update().clone();
+            match current {
+                HandleState::Ready(client) => return Ok(client),
+                HandleState::Closed => return Err(anyhow!("client closed")),
+                HandleState::Pending => {}
+            }
+            if state.changed().await.is_err() {
+                return Err(anyhow!("client closed"));
+            }
+        }
+    }
+
+    /// The inner client if it is connected and authorized now, without waiting.
+    pub fn try_client(&self) -> Option<grammers_client::Client> {
+        match &*self.state.borrow() {
+            HandleState::Ready(client) => Some(client.clone()),
+            _ => None,
+        }
+    }
+
+    /// The persistent outbound queue of the client, if configured
+    pub fn outbound_queue(&self) -> Option<&Arc<OutboundQueue>> {
+        self.outbound.as_ref()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        matches!(&*self.state.borrow(), HandleState::Ready(_))
+    }
+
+    pub fn is_closed(&self) -> bool {
+        matches!(&*self.state.borrow(), HandleState::Closed)
+    }
+
+    /// Export the current session as a string session
+    pub async fn export_string_session(&self) -> Result<String> {
+        let client = self.client().await?;
+        client.sync_update_state();
+        Ok(crate::session::export_string_session(client.session()))
+    }
+
+    /// Log out the account, then the running client stops with `ClientExit::LoggedOut`
+    /// and the stored session is deleted through the `SessionStore`, also if the request
+    /// fails. Waits until the client stopped.
+    pub async fn log_out(&self) -> Result<()> {
+        let client = self.client().await?;
+        let mut state = self.state.clone();
+        // stop first, a reconnect while logging out would find the session revoked
+        // and login again
+        self.shutdown.send_replace(Some(ClientExit::LoggedOut));
+        let result = client.invoke(&tl::functions::auth::LogOut {}).await;
+        let _ = state
+            .wait_for(|state| matches!(state, HandleState::Closed))
+            .await;
+        result?;
+        tracing::info!("log out");
+        Ok(())
+    }
+
+    /// The active sessions of the account, including this one (`current` is true)
+    pub async fn authorizations(&self) -> Result<Vec<tl::types::Authorization>> {
+        let tl::enums::account::Authorizations::Authorizations(authorizations) = self
+            .invoke(&tl::functions::account::GetAuthorizations {})
+            .await?;
+        Ok(authorizations
+            .authorizations
+            .into_iter()
+            .map(|tl::enums::Authorization::Authorization(authorization)| authorization)
+            .collect())
+    }
+
+    /// Terminate another session by the `hash` of its authorization
+    pub async fn terminate_authorization(&self, hash: i64) -> Result<()> {
+        self.invoke(&tl::functions::account::ResetAuthorization { hash })
+            .await?;
+        Ok(())
+    }
+
+    /// Terminate every session of the account except this one
+    pub async fn terminate_other_authorizations(&self) -> Result<()> {
+        self.invoke(&tl::functions::auth::ResetAuthorizations {})
+            .await?;
+        Ok(())
+    }
+
+    /// Call `f` with the inner client, retrying by the `RetryPolicy`,
+    /// `f` is called again on retry (with the latest inner client)
+    ///
+    /// ```no_run
+    /// # async fn f(handle: teleser::ClientHandle, chat: teleser::re_exports::grammers_session::PackedChat) -> teleser::Result<()> {
+    /// use teleser::re_exports::grammers_client::InputMessage;
+    /// handle
+    ///     .call(|client| async move {
+    ///         client.send_message(chat, InputMessage::text("hi").silent(true)).await
+    ///     })
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn call<T, F, Fut>(&self, f: F) -> Result<T>
+    where
+        F: FnMut(grammers_client::Client) -> Fut,
+        Fut: Future<Output = std::result::Result<T, InvocationError>>,
+    {
+        self.call_with(f, true).await
+    }
+
+    /// Like `call`, for requests that must not run twice, like sending a message:
+    /// a lost connection is not retried because the request may have reached Telegram,
+    /// and a new `random_id` would deliver it twice
+    pub async fn call_send<T, F, Fut>(&self, f: F) -> Result<T>
+    where
+        F: FnMut(grammers_client::Client) -> Fut,
+        Fut: Future<Output = std::result::Result<T, InvocationError>>,
+    {
+        self.call_with(f, false).await
+    }
+
+    async fn call_with<T, F, Fut>(&self, mut f: F, idempotent: bool) -> Result<T>
+    where
+        F: FnMut(grammers_client::Client) -> Fut,
+        Fut: Future<Output = std::result::Result<T, InvocationError>>,
+    {
+        let mut retries = 0;
+        loop {
+            let err = match f(self.client().await?).await {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+            match self.retry.retry_after(&err, &mut retries, idempotent) {
+                Some(wait) => sleep(wait).await,
+                None => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Retried like `call`, use `call_send` for requests not safe to run twice
+    pub async fn invoke<R: tl::RemoteCall>(&self, request: &R) -> Result<R::Return> {
+        self.call(|client| async move { client.invoke(request).await })
+            .await
+    }
+
+    pub async fn get_me(&self) -> Result<User> {
+        self.call(|client| async move { client.get_me().await })
+            .await
+    }
+
+    /// Wait for the `RateLimiter` of the client to allow a send to the chat,
+    /// returns at once without a rate limit. The helpers below already wait.
+    pub async fn wait_send_slot<C: Into<PackedChat>>(&self, chat: C, priority: SendPriority) {
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire(chat.into(), priority).await;
+        }
+    }
+
+    /// `message` is cloned on retry, use `call` for a not cloneable `InputMessage`
+    pub async fn send_message<C: Into<PackedChat>, M: Into<InputMessage> + Clone>(
+        &self,
+        chat: C,
+        message: M,
+    ) -> Result<Message> {
+        self.send_message_with_priority(chat, message, SendPriority::Normal)
+            .await
+    }
+
+    pub async fn send_message_with_priority<C: Into<PackedChat>, M: Into<InputMessage> + Clone>(
+        &self,
+        chat: C,
+        message: M,
+        priority: SendPriority,
+    ) -> Result<Message> {
+        let chat = chat.into();
+        self.wait_send_slot(chat, priority).await;
+        self.call_send(|client| {
+            let message = message.clone();
+            async move { client.send_message(chat, message).await }
+        })
+        .await
+    }
+
+    pub async fn edit_message<C: Into<PackedChat>, M: Into<InputMessage> + Clone>(
+        &self,
+        chat: C,
+        message_id: i32,
+        new_message: M,
+    ) -> Result<()> {
+        let chat = chat.into();
+        self.call(|client| {
+            let new_message = new_message.clone();
+            async move { client.edit_message(chat, message_id, new_message).await }
+        })
+        .await
+    }
+
+    pub async fn delete_messages<C: Into<PackedChat>>(
+        &self,
+        chat: C,
+        message_ids: &[i32],
+    ) -> Result<usize> {
+        let chat = chat.into();
+        self.call(|client| async move { client.delete_messages(chat, message_ids).await })
+            .await
+    }
+
+    pub async fn forward_messages<C: Into<PackedChat>, S: Into<PackedChat>>(
+        &self,
+        destination: C,
+        message_ids: &[i32],
+        source: S,
+    ) -> Result<Vec<Option<Message>>> {
+        let (destination, source) = (destination.into(), source.into());
+        self.wait_send_slot(destination, SendPriority::Normal).await;
+        self.call_send(|client| async move {
+            client
+                .forward_messages(destination, message_ids, source)
+                .await
+        })
+        .await
+    }
+
+    /// Send a text of any length as several messages, see `FormattedText::split`
+    pub async fn send_long_message<C: Into<PackedChat>, T: Into<FormattedText>>(
+        &self,
+        chat: C,
+        text: T,
+    ) -> Result<Vec<Message>> {
+        let chat = chat.into();
+        let mut messages = vec![];
+        for part in text.into().split(MAX_MESSAGE_LENGTH) {
+            messages.push(self.send_message(chat, part).await?);
+        }
+        Ok(messages)
+    }
+
+    /// Send a media with a caption of any length: `media` adds the media to the first message
+    /// (like `|message| message.photo(uploaded.clone())`), the rest of the caption follows as text
+    pub async fn send_long_caption<C, T, F>(
+        &self,
+        chat: C,
+        caption: T,
+        media: F,
+    ) -> Result<Vec<Message>>
+    where
+        C: Into<PackedChat>,
+        T: Into<FormattedText>,
+        F: Fn(InputMessage) -> InputMessage,
+    {
+        let chat = chat.into();
+        let (caption, rest) = caption.into().split_caption();
+        self.wait_send_slot(chat, SendPriority::Normal).await;
+        let first = self
+            .call_send(|client| {
+                let message = media(caption.clone().into());
+                async move { client.send_message(chat, message).await }
+            })
+            .await?;
+        let mut messages = vec![first];
+        for part in rest {
+            messages.push(self.send_message(chat, part).await?);
+        }
+        Ok(messages)
+    }
+
+    /// Send the message to every chat in the background, at low priority under the
+    /// rate limit, skipping the users who blocked the account
+    pub fn broadcast<M>(
+        &self,
+        chats: Vec<PackedChat>,
+        message: M,
+        options: BroadcastOptions,
+    ) -> BroadcastHandle
+    where
+        M: Into<InputMessage> + Clone + Send + Sync + 'static,
+    {
+        spawn_broadcast(self.clone(), chats, message, options)
+    }
+}
@@ -1,3 +1,5 @@
+use crate::schedule::Schedule;
+use crate::ClientHandle;
 use async_trait::async_trait;
 use grammers_client::types::{CallbackQuery, InlineQuery, Message, MessageDeletion};
 use grammers_client::Update;
@@ -24,6 +26,13 @@ process_trait!(CallbackQueryProcess, CallbackQuery);
 process_trait!(InlineQueryProcess, InlineQuery);
 process_trait!(RawProcess, tl::enums::Update);
 
+/// A job run on a schedule, from the authorization until the client stops
+#[async_trait]
+pub trait ScheduledProcess: Sync + Send {
+    fn schedule(&self) -> Schedule;
+    async fn handle(&self, client: ClientHandle) -> anyhow::Result<()>;
+}
+
 pub enum Process {
     UpdateProcess(Box<dyn UpdateProcess>),
     NewMessageProcess(Box<dyn NewMessageProcess>),
@@ -32,6 +41,7 @@ pub enum Process {
     CallbackQueryProcess(Box<dyn CallbackQueryProcess>),
     InlineQueryProcess(Box<dyn InlineQueryProcess>),
     RawProcess(Box<dyn RawProcess>),
+    ScheduledProcess(Box<dyn ScheduledProcess>),
 }
 
 pub struct Module {
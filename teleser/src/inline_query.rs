@@ -0,0 +1,436 @@
+use crate::{ClientHandle, Result};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use grammers_client::reply_markup::{self, ReplyMarkup};
+use grammers_client::types::InlineQuery;
+use grammers_tl_types as tl;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Results of one answer Telegram accepts
+pub const MAX_INLINE_RESULTS: usize = 50;
+/// Longest result id, in bytes
+pub const MAX_INLINE_RESULT_ID: usize = 64;
+
+#[derive(Debug, Clone)]
+enum InlineContent {
+    Article { url: Option<String> },
+    PhotoUrl { url: String },
+    Photo(tl::enums::InputPhoto),
+    DocumentUrl { url: String, mime_type: String },
+    Document(tl::enums::InputDocument),
+}
+
+/// One result of an inline query answer: an article (a text message), a photo or a document
+///
+/// ```
+/// use teleser::InlineResult;
+/// let result = InlineResult::article("1", "Hello", "Hello world")
+///     .with_description("send a greeting");
+/// ```
+#[derive(Debug, Clone)]
+pub struct InlineResult {
+    id: String,
+    title: Option<String>,
+    description: Option<String>,
+    thumb_url: Option<String>,
+    content: InlineContent,
+    // text of an article, caption of a media
+    text: String,
+    entities: Option<Vec<tl::enums::MessageEntity>>,
+    reply_markup: Option<tl::enums::ReplyMarkup>,
+    link_preview: bool,
+}
+
+impl InlineResult {
+    fn new(id: String, content: InlineContent, text: String) -> Self {
+        Self {
+            id,
+            title: None,
+            description: None,
+            thumb_url: None,
+            content,
+            text,
+            entities: None,
+            reply_markup: None,
+            link_preview: true,
+        }
+    }
+
+    /// Send `text` when chosen
+    pub fn article<I: Into<String>, T: Into<String>, M: Into<String>>(
+        id: I,
+        title: T,
+        text: M,
+    ) -> Self {
+        Self::new(id.into(), InlineContent::Article { url: None }, text.into()).with_title(title)
+    }
+
+    /// A JPEG photo Telegram downloads from `url`
+    pub fn photo_url<I: Into<String>, U: Into<String>>(id: I, url: U) -> Self {
+        let url = url.into();
+        Self::new(
+            id.into(),
+            InlineContent::PhotoUrl { url: url.clone() },
+            String::new(),
+        )
+        .with_thumb_url(url)
+    }
+
+    /// A photo already on Telegram, like the photo of a received message
+    pub fn photo<I: Into<String>>(id: I, photo: tl::enums::InputPhoto) -> Self {
+        Self::new(id.into(), InlineContent::Photo(photo), String::new())
+    }
+
+    /// A file Telegram downloads from `url`, `mime_type` is `application/pdf` or `application/zip`
+    pub fn document_url<I: Into<String>, T: Into<String>, U: Into<String>, M: Into<String>>(
+        id: I,
+        title: T,
+        url: U,
+        mime_type: M,
+    ) -> Self {
+        Self::new(
+            id.into(),
+            InlineContent::DocumentUrl {
+                url: url.into(),
+                mime_type: mime_type.into(),
+            },
+            String::new(),
+        )
+        .with_title(title)
+    }
+
+    /// A document already on Telegram
+    pub fn document<I: Into<String>, T: Into<String>>(
+        id: I,
+        title: T,
+        document: tl::enums::InputDocument,
+    ) -> Self {
+        Self::new(id.into(), InlineContent::Document(document), String::new()).with_title(title)
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn with_title<T: Into<String>>(mut self, title: T) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn with_description<D: Into<String>>(mut self, description: D) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn with_thumb_url<U: Into<String>>(mut self, thumb_url: U) -> Self {
+        self.thumb_url = Some(thumb_url.into());
+        self
+    }
+
+    /// URL shown with an article
+    pub fn with_url<U: Into<String>>(mut self, url: U) -> Self {
+        if let InlineContent::Article { url: article_url } = &mut self.content {
+            *article_url = Some(url.into());
+        }
+        self
+    }
+
+    /// Text of an article, caption of a photo or a document
+    pub fn with_text<T: Into<String>>(mut self, text: T) -> Self {
+        self.text = text.into();
+        self
+    }
+
+    /// Formatting of the text, like the entities of `parsers::parse_markdown_message`
+    pub fn with_entities(mut self, entities: Vec<tl::enums::MessageEntity>) -> Self {
+        self.entities = Some(entities);
+        self
+    }
+
+    pub fn with_keyboard(mut self, keyboard: &reply_markup::Inline) -> Self {
+        self.reply_markup = Some(keyboard.to_reply_markup().raw);
+        self
+    }
+
+    pub fn with_link_preview(mut self, link_preview: bool) -> Self {
+        self.link_preview = link_preview;
+        self
+    }
+
+    fn to_raw(&self) -> Result<tl::enums::InputBotInlineResult> {
+        if self.id.is_empty() || self.id.len() > MAX_INLINE_RESULT_ID {
+            return Err(anyhow!(
+                "inline result id \"{}\" must be 1 to {MAX_INLINE_RESULT_ID} bytes",
+                self.id
+            ));
+        }
+        let media_message = || {
+            tl::types::InputBotInlineMessageMediaAuto {
+                invert_media: false,
+                message: self.text.clone(),
+                entities: self.entities.clone(),
+                reply_markup: self.reply_markup.clone(),
+            }
+            .into()
+        };
+        let web_document = |url: &str, mime_type: &str| {
+            tl::types::InputWebDocument {
+                url: url.to_owned(),
+                size: 0,
+                mime_type: mime_type.to_owned(),
+                attributes: vec![],
+            }
+            .into()
+        };
+        let thumb = self
+            .thumb_url
+            .as_deref()
+            .map(|url| web_document(url, "image/jpeg"));
+        Ok(match &self.content {
+            InlineContent::Article { url } => tl::types::InputBotInlineResult {
+                id: self.id.clone(),
+                r#type: "article".into(),
+                title: self.title.clone(),
+                description: self.description.clone(),
+                url: url.clone(),
+                thumb,
+                content: None,
+                send_message: tl::types::InputBotInlineMessageText {
+                    no_webpage: !self.link_preview,
+                    invert_media: false,
+                    message: self.text.clone(),
+                    entities: self.entities.clone(),
+                    reply_markup: self.reply_markup.clone(),
+                }
+                .into(),
+            }
+            .into(),
+            InlineContent::PhotoUrl { url } => tl::types::InputBotInlineResult {
+                id: self.id.clone(),
+                r#type: "photo".into(),
+                title: self.title.clone(),
+                description: self.description.clone(),
+                url: None,
+                thumb,
+                content: Some(web_document(url, "image/jpeg")),
+                send_message: media_message(),
+            }
+            .into(),
+            InlineContent::DocumentUrl { url, mime_type } => tl::types::InputBotInlineResult {
+                id: self.id.clone(),
+                r#type: "file".into(),
+                title: self.title.clone(),
+                description: self.description.clone(),
+                url: None,
+                thumb,
+                content: Some(web_document(url, mime_type)),
+                send_message: media_message(),
+            }
+            .into(),
+            InlineContent::Photo(photo) => tl::types::InputBotInlineResultPhoto {
+                id: self.id.clone(),
+                r#type: "photo".into(),
+                photo: photo.clone(),
+                send_message: media_message(),
+            }
+            .into(),
+            InlineContent::Document(document) => tl::types::InputBotInlineResultDocument {
+                id: self.id.clone(),
+                r#type: "file".into(),
+                title: self.title.clone(),
+                description: self.description.clone(),
+                document: document.clone(),
+                send_message: media_message(),
+            }
+            .into(),
+        })
+    }
+}
+
+/// Async source of the results of a query, read from the start on every page
+#[async_trait]
+pub trait InlineResults: Send {
+    /// The next result, `None` at the end
+    async fn next_result(&mut self) -> Result<Option<InlineResult>>;
+}
+
+#[async_trait]
+impl<I: Iterator<Item = InlineResult> + Send> InlineResults for I {
+    async fn next_result(&mut self) -> Result<Option<InlineResult>> {
+        Ok(self.next())
+    }
+}
+
+struct CachedPage {
+    results: Vec<InlineResult>,
+    next_offset: Option<String>,
+    expires_at: Instant,
+}
+
+/// Answer inline queries page by page.
+///
+/// The offset Telegram sends back when the user scrolls is the count of results
+/// already shown, so a page skips them in `InlineResults` and takes the next `page_size`.
+/// Keep one answerer in the handler, the pages are cached by query text
+/// (and by user for private answers) for `result_cache`.
+pub struct InlineAnswerer {
+    page_size: usize,
+    cache_time: Duration,
+    private: bool,
+    gallery: bool,
+    switch_pm: Option<(String, String)>,
+    result_cache: Option<Duration>,
+    cache: Mutex<HashMap<(i64, String, usize), CachedPage>>,
+}
+
+impl Default for InlineAnswerer {
+    fn default() -> Self {
+        Self {
+            page_size: MAX_INLINE_RESULTS,
+            cache_time: Duration::from_secs(300),
+            private: false,
+            gallery: false,
+            switch_pm: None,
+            result_cache: None,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl InlineAnswerer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Results of one page, at most `MAX_INLINE_RESULTS`
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.clamp(1, MAX_INLINE_RESULTS);
+        self
+    }
+
+    /// How long Telegram caches the answer of a query
+    pub fn with_cache_time(mut self, cache_time: Duration) -> Self {
+        self.cache_time = cache_time;
+        self
+    }
+
+    /// The results depend on the user, cached by user instead of for everyone
+    pub fn with_private(mut self, private: bool) -> Self {
+        self.private = private;
+        self
+    }
+
+    /// Show the results as a grid
+    pub fn with_gallery(mut self, gallery: bool) -> Self {
+        self.gallery = gallery;
+        self
+    }
+
+    /// A button above the results opening the private chat with `/start start_param`
+    pub fn with_switch_pm<T: Into<String>, S: Into<String>>(
+        mut self,
+        text: T,
+        start_param: S,
+    ) -> Self {
+        self.switch_pm = Some((text.into(), start_param.into()));
+        self
+    }
+
+    /// Keep the answered pages in memory this long, `None` reads `InlineResults` on every query
+    pub fn with_result_cache(mut self, result_cache: Option<Duration>) -> Self {
+        self.result_cache = result_cache;
+        self
+    }
+
+    /// Answer the page of `query` its offset asks, with `next_offset` set while results remain
+    pub async fn answer<R: InlineResults>(
+        &self,
+        client: &ClientHandle,
+        query: &InlineQuery,
+        results: R,
+    ) -> Result<()> {
+        let offset = query.offset().parse::<usize>().unwrap_or_default();
+        let user = if self.private { query.sender().id() } else { 0 };
+        let key = (user, query.text().to_owned(), offset);
+        let (page, next_offset) = match self.cached(&key) {
+            Some(cached) => cached,
+            None => {
+                let (page, next_offset) = self.read_page(results, offset).await?;
+                if let Some(ttl) = self.result_cache {
+                    let mut cache = self.cache.lock().unwrap();
+                    let now = Instant::now();
+                    cache.retain(|_, cached| cached.expires_at > now);
+                    cache.insert(
+                        key,
+                        CachedPage {
+                            results: page.clone(),
+                            next_offset: next_offset.clone(),
+                            expires_at: now + ttl,
+                        },
+                    );
+                }
+                (page, next_offset)
+            }
+        };
+        let results = page
+            .iter()
+            .map(InlineResult::to_raw)
+            .collect::<Result<Vec<_>>>()?;
+        client
+            .invoke(&tl::functions::messages::SetInlineBotResults {
+                gallery: self.gallery,
+                private: self.private,
+                query_id: query.query_id(),
+                results,
+                cache_time: self.cache_time.as_secs().min(i32::MAX as u64) as i32,
+                next_offset,
+                switch_pm: self.switch_pm.as_ref().map(|(text, start_param)| {
+                    tl::types::InlineBotSwitchPm {
+                        text: text.clone(),
+                        start_param: start_param.clone(),
+                    }
+                    .into()
+                }),
+                switch_webview: None,
+            })
+            .await?;
+        Ok(())
+    }
+
+    fn cached(&self, key: &(i64, String, usize)) -> Option<(Vec<InlineResult>, Option<String>)> {
+        let cache = self.cache.lock().unwrap();
+        let cached = cache.get(key)?;
+        if cached.expires_at <= Instant::now() {
+            return None;
+        }
+        Some((cached.results.clone(), cached.next_offset.clone()))
+    }
+
+    async fn read_page<R: InlineResults>(
+        &self,
+        mut results: R,
+        offset: usize,
+    ) -> Result<(Vec<InlineResult>, Option<String>)> {
+        for _ in 0..offset {
+            if results.next_result().await?.is_none() {
+                return Ok((vec![], None));
+            }
+        }
+        let mut page = Vec::with_capacity(self.page_size);
+        while page.len() < self.page_size {
+            match results.next_result().await? {
+                Some(result) => page.push(result),
+                None => return Ok((page, None)),
+            }
+        }
+        // one more result tells if there is a next page
+        let next_offset = results
+            .next_result()
+            .await?
+            .map(|_| (offset + page.len()).to_string());
+        Ok((page, next_offset))
+    }
+}
@@ -0,0 +1,298 @@
+use crate::Result;
+use anyhow::anyhow;
+use grammers_client::{button, reply_markup};
+
+/// Longest callback data Telegram accepts, in bytes
+pub const MAX_CALLBACK_DATA: usize = 64;
+pub const MAX_INLINE_ROW_BUTTONS: usize = 8;
+pub const MAX_INLINE_BUTTONS: usize = 100;
+pub const MAX_REPLY_ROW_BUTTONS: usize = 12;
+pub const MAX_REPLY_BUTTONS: usize = 300;
+
+/// Typed payload of a callback button, read back from `CallbackQuery::data`
+pub trait CallbackData: Sized {
+    fn to_data(&self) -> Vec<u8>;
+
+    fn from_data(data: &[u8]) -> Result<Self>;
+}
+
+impl CallbackData for Vec<u8> {
+    fn to_data(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn from_data(data: &[u8]) -> Result<Self> {
+        Ok(data.to_vec())
+    }
+}
+
+impl CallbackData for String {
+    fn to_data(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn from_data(data: &[u8]) -> Result<Self> {
+        Ok(String::from_utf8(data.to_vec())?)
+    }
+}
+
+enum InlineButton {
+    Callback(Vec<u8>),
+    Url(String),
+    SwitchInline { query: String, current_chat: bool },
+}
+
+/// Buttons under a message, checked against the Telegram limits on `build`
+///
+/// ```
+/// use teleser::InlineKeyboard;
+/// let keyboard = InlineKeyboard::new()
+///     .callback("Yes", &"vote:yes".to_string())
+///     .callback("No", &"vote:no".to_string())
+///     .row()
+///     .url("Rules", "https://example.com/rules")
+///     .build()
+///     .unwrap();
+/// // InputMessage::text("Vote").reply_markup(&keyboard)
+/// ```
+#[derive(Default)]
+pub struct InlineKeyboard {
+    rows: Vec<Vec<(String, InlineButton)>>,
+    columns: Option<usize>,
+}
+
+impl InlineKeyboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lay the buttons out in rows of at most `columns`, `row` still starts a new row
+    pub fn with_columns(mut self, columns: usize) -> Self {
+        self.columns = Some(columns);
+        self
+    }
+
+    /// Start a new row
+    pub fn row(mut self) -> Self {
+        self.rows.push(vec![]);
+        self
+    }
+
+    pub fn callback<T: Into<String>, D: CallbackData>(self, text: T, data: &D) -> Self {
+        self.push(text, InlineButton::Callback(data.to_data()))
+    }
+
+    pub fn url<T: Into<String>, U: Into<String>>(self, text: T, url: U) -> Self {
+        self.push(text, InlineButton::Url(url.into()))
+    }
+
+    /// Let the user pick a chat, then type the bot username and `query` there
+    pub fn switch_inline<T: Into<String>, Q: Into<String>>(self, text: T, query: Q) -> Self {
+        self.push(
+            text,
+            InlineButton::SwitchInline {
+                query: query.into(),
+                current_chat: false,
+            },
+        )
+    }
+
+    /// Type the bot username and `query` in the current chat
+    pub fn switch_inline_current<T: Into<String>, Q: Into<String>>(
+        self,
+        text: T,
+        query: Q,
+    ) -> Self {
+        self.push(
+            text,
+            InlineButton::SwitchInline {
+                query: query.into(),
+                current_chat: true,
+            },
+        )
+    }
+
+    fn push<T: Into<String>>(mut self, text: T, button: InlineButton) -> Self {
+        push_button(&mut self.rows, (text.into(), button));
+        self
+    }
+
+    pub fn build(self) -> Result<reply_markup::Inline> {
+        let rows = layout(self.rows, self.columns);
+        check_limits(&rows, MAX_INLINE_ROW_BUTTONS, MAX_INLINE_BUTTONS)?;
+        let mut markup = Vec::with_capacity(rows.len());
+        for row in rows {
+            let mut buttons = Vec::with_capacity(row.len());
+            for (text, button) in row {
+                check_text(&text)?;
+                buttons.push(match button {
+                    InlineButton::Callback(data) => {
+                        if data.is_empty() || data.len() > MAX_CALLBACK_DATA {
+                            return Err(anyhow!(
+                                "callback data of button \"{text}\" is {} bytes, must be 1 to {MAX_CALLBACK_DATA}",
+                                data.len()
+                            ));
+                        }
+                        button::inline(text, data)
+                    }
+                    InlineButton::Url(url) => {
+                        if url.is_empty() {
+                            return Err(anyhow!("url of button \"{text}\" is empty"));
+                        }
+                        button::url(text, url)
+                    }
+                    InlineButton::SwitchInline {
+                        query,
+                        current_chat: true,
+                    } => button::switch_inline(text, query),
+                    InlineButton::SwitchInline { query, .. } => {
+                        button::switch_inline_elsewhere(text, query)
+                    }
+                });
+            }
+            markup.push(buttons);
+        }
+        Ok(reply_markup::inline(markup))
+    }
+}
+
+enum ReplyButton {
+    Text,
+    RequestContact,
+    RequestLocation,
+}
+
+/// A custom keyboard replacing the keyboard of the user, checked against the Telegram limits on `build`
+#[derive(Default)]
+pub struct ReplyKeyboard {
+    rows: Vec<Vec<(String, ReplyButton)>>,
+    columns: Option<usize>,
+    resize: bool,
+    one_time: bool,
+    selective: bool,
+}
+
+impl ReplyKeyboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lay the buttons out in rows of at most `columns`, `row` still starts a new row
+    pub fn with_columns(mut self, columns: usize) -> Self {
+        self.columns = Some(columns);
+        self
+    }
+
+    /// Fit the keyboard height to the buttons
+    pub fn with_resize(mut self, resize: bool) -> Self {
+        self.resize = resize;
+        self
+    }
+
+    /// Hide the keyboard once a button is pressed
+    pub fn with_one_time(mut self, one_time: bool) -> Self {
+        self.one_time = one_time;
+        self
+    }
+
+    /// Show the keyboard only to the mentioned users and the sender of the replied message
+    pub fn with_selective(mut self, selective: bool) -> Self {
+        self.selective = selective;
+        self
+    }
+
+    /// Start a new row
+    pub fn row(mut self) -> Self {
+        self.rows.push(vec![]);
+        self
+    }
+
+    /// A button sending its text
+    pub fn text<T: Into<String>>(self, text: T) -> Self {
+        self.push(text, ReplyButton::Text)
+    }
+
+    /// A button sharing the phone number of the user
+    pub fn request_contact<T: Into<String>>(self, text: T) -> Self {
+        self.push(text, ReplyButton::RequestContact)
+    }
+
+    pub fn request_location<T: Into<String>>(self, text: T) -> Self {
+        self.push(text, ReplyButton::RequestLocation)
+    }
+
+    fn push<T: Into<String>>(mut self, text: T, button: ReplyButton) -> Self {
+        push_button(&mut self.rows, (text.into(), button));
+        self
+    }
+
+    pub fn build(self) -> Result<reply_markup::Keyboard> {
+        let rows = layout(self.rows, self.columns);
+        check_limits(&rows, MAX_REPLY_ROW_BUTTONS, MAX_REPLY_BUTTONS)?;
+        let mut markup = Vec::with_capacity(rows.len());
+        for row in rows {
+            let mut buttons = Vec::with_capacity(row.len());
+            for (text, button) in row {
+                check_text(&text)?;
+                buttons.push(match button {
+                    ReplyButton::Text => button::text(text),
+                    ReplyButton::RequestContact => button::request_phone(text),
+                    ReplyButton::RequestLocation => button::request_geo(text),
+                });
+            }
+            markup.push(buttons);
+        }
+        let mut keyboard = reply_markup::keyboard(markup);
+        if self.resize {
+            keyboard = keyboard.fit_size();
+        }
+        if self.one_time {
+            keyboard = keyboard.single_use();
+        }
+        if self.selective {
+            keyboard = keyboard.selective();
+        }
+        Ok(keyboard)
+    }
+}
+
+fn push_button<B>(rows: &mut Vec<Vec<B>>, button: B) {
+    match rows.last_mut() {
+        Some(row) => row.push(button),
+        None => rows.push(vec![button]),
+    }
+}
+
+/// Split the rows longer than `columns`, and drop the empty ones
+fn layout<B>(rows: Vec<Vec<B>>, columns: Option<usize>) -> Vec<Vec<B>> {
+    let columns = columns.unwrap_or(usize::MAX).max(1);
+    let mut result = vec![];
+    for row in rows {
+        let mut row = row.into_iter().peekable();
+        while row.peek().is_some() {
+            result.push(row.by_ref().take(columns).collect());
+        }
+    }
+    result
+}
+
+fn check_limits<B>(rows: &[Vec<B>], max_row: usize, max_total: usize) -> Result<()> {
+    if let Some(row) = rows.iter().position(|row| row.len() > max_row) {
+        return Err(anyhow!(
+            "row {row} of the keyboard has {} buttons, at most {max_row}",
+            rows[row].len()
+        ));
+    }
+    let total: usize = rows.iter().map(Vec::len).sum();
+    if total > max_total {
+        return Err(anyhow!("keyboard has {total} buttons, at most {max_total}"));
+    }
+    Ok(())
+}
+
+fn check_text(text: &str) -> Result<()> {
+    if text.is_empty() {
+        return Err(anyhow!("button text is empty"));
+    }
+    Ok(())
+}
@@ -1,11 +1,35 @@
+mod auth;
+mod broadcast;
 mod client;
+mod handle;
 mod handler;
+mod inline_query;
+mod keyboard;
+mod limit;
+mod outbound;
+mod pool;
 pub mod re_exports;
+mod retry;
+mod schedule;
+mod session;
+mod split;
 mod traits;
 
 pub use anyhow::Result;
+pub use auth::*;
+pub use broadcast::*;
 pub use client::*;
 pub use grammers_client::Client as InnerClient;
+pub use handle::*;
 pub use handler::*;
+pub use inline_query::*;
+pub use keyboard::*;
+pub use limit::*;
+pub use outbound::*;
+pub use pool::*;
+pub use retry::*;
+pub use schedule::*;
+pub use session::*;
+pub use split::*;
 pub use teleser_gen::*;
 pub use traits::*;
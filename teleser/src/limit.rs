@@ -0,0 +1,246 @@
+use grammers_session::{PackedChat, PackedType};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::{sleep_until, Instant};
+
+/// At most `count` sends `per` duration
+#[derive(Debug, Clone, Copy)]
+pub struct Rate {
+    pub count: u32,
+    pub per: Duration,
+}
+
+impl Rate {
+    pub const fn new(count: u32, per: Duration) -> Self {
+        Self { count, per }
+    }
+}
+
+/// Outbound limits, defaults follow the limits Telegram documents for bots
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    /// All the sends of the client
+    pub global: Rate,
+    /// Sends to one private chat
+    pub chat: Rate,
+    /// Sends to one group or channel
+    pub group: Rate,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            global: Rate::new(30, Duration::from_secs(1)),
+            chat: Rate::new(1, Duration::from_secs(1)),
+            group: Rate::new(20, Duration::from_secs(60)),
+        }
+    }
+}
+
+/// Sends with a higher priority are let through first when they are queued
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SendPriority {
+    /// Replies to users
+    High,
+    Normal,
+    /// Bulk sends, like broadcasts
+    Low,
+}
+
+/// Queue the sends of a client to stay under the `RateLimit`.
+///
+/// A send waits for a slot instead of failing, and a waiting send is let through
+/// only if no send of a higher priority (or queued earlier) could go now.
+///
+/// ```
+/// # teleser::re_exports::tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// use std::sync::{Arc, Mutex};
+/// use std::time::Duration;
+/// use teleser::re_exports::grammers_session::{PackedChat, PackedType};
+/// use teleser::re_exports::tokio;
+/// use teleser::{Rate, RateLimit, RateLimiter, SendPriority};
+///
+/// let limiter = Arc::new(RateLimiter::new(RateLimit {
+///     global: Rate::new(1, Duration::from_millis(100)),
+///     ..RateLimit::default()
+/// }));
+/// let user = |id| PackedChat {
+///     ty: PackedType::User,
+///     id,
+///     access_hash: Some(0),
+/// };
+/// // take the only slot, so the next sends wait
+/// limiter.acquire(user(1), SendPriority::Normal).await;
+/// let sent = Arc::new(Mutex::new(vec![]));
+/// let mut tasks = vec![];
+/// for (id, priority) in [
+///     (2, SendPriority::Low),
+///     (3, SendPriority::Normal),
+///     (4, SendPriority::High),
+///     (5, SendPriority::Normal),
+/// ] {
+///     let (limiter, sent) = (limiter.clone(), sent.clone());
+///     tasks.push(tokio::spawn(async move {
+///         limiter.acquire(user(id), priority).await;
+///         sent.lock().unwrap().push(id);
+///     }));
+///     // queue them in this order
+///     tokio::time::sleep(Duration::from_millis(10)).await;
+/// }
+/// for task in tasks {
+///     task.await.unwrap();
+/// }
+/// // by priority, then in queue order
+/// assert_eq!(*sent.lock().unwrap(), [4, 3, 5, 2]);
+/// # });
+/// ```
+pub struct RateLimiter {
+    limit: RateLimit,
+    state: Mutex<LimiterState>,
+    released: Notify,
+}
+
+#[derive(Default)]
+struct LimiterState {
+    next_ticket: u64,
+    global: VecDeque<Instant>,
+    chats: HashMap<i64, VecDeque<Instant>>,
+    // (priority, ticket) -> (chat, rate of the chat)
+    waiting: BTreeMap<(SendPriority, u64), (i64, Rate)>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            state: Mutex::new(LimiterState::default()),
+            released: Notify::new(),
+        }
+    }
+
+    pub fn limit(&self) -> &RateLimit {
+        &self.limit
+    }
+
+    /// Wait until a message can be sent to the chat
+    pub async fn acquire(&self, chat: PackedChat, priority: SendPriority) {
+        let key = chat_key(&chat);
+        let chat_rate = match chat.ty {
+            PackedType::User | PackedType::Bot => self.limit.chat,
+            _ => self.limit.group,
+        };
+        let ticket = {
+            let mut state = self.state.lock().unwrap();
+            let ticket = state.next_ticket;
+            state.next_ticket += 1;
+            state.waiting.insert((priority, ticket), (key, chat_rate));
+            ticket
+        };
+        // leave the queue even if the send is cancelled
+        let _waiting = Waiting {
+            limiter: self,
+            id: (priority, ticket),
+        };
+        loop {
+            let released = self.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+            let ready_at = {
+                let mut state = self.state.lock().unwrap();
+                let now = Instant::now();
+                state.expire(now, &self.limit);
+                let ready_at = state.ready_at(key, chat_rate, &self.limit, now);
+                if ready_at <= now && !state.blocked_by_earlier((priority, ticket), now) {
+                    state.global.push_back(now);
+                    state.chats.entry(key).or_default().push_back(now);
+                    None
+                } else {
+                    Some(ready_at.max(now + Duration::from_millis(10)))
+                }
+            };
+            match ready_at {
+                None => return,
+                Some(ready_at) => {
+                    tokio::select! {
+                        _ = sleep_until(ready_at) => {}
+                        _ = released => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Waiting<'a> {
+    limiter: &'a RateLimiter,
+    id: (SendPriority, u64),
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.limiter.state.lock().unwrap().waiting.remove(&self.id);
+        self.limiter.released.notify_waiters();
+    }
+}
+
+impl LimiterState {
+    fn expire(&mut self, now: Instant, limit: &RateLimit) {
+        expire(&mut self.global, now, limit.global.per);
+        let keep = limit.chat.per.max(limit.group.per);
+        self.chats.retain(|_, sends| {
+            expire(sends, now, keep);
+            !sends.is_empty()
+        });
+    }
+
+    /// When the global and the chat rate allow one more send
+    fn ready_at(&self, key: i64, chat_rate: Rate, limit: &RateLimit, now: Instant) -> Instant {
+        let global = free_at(&self.global, limit.global, now);
+        let chat = match self.chats.get(&key) {
+            Some(sends) => free_at(sends, chat_rate, now),
+            None => now,
+        };
+        global.max(chat)
+    }
+
+    /// A send queued before (or with a higher priority) could go now, so let it take the slot
+    fn blocked_by_earlier(&self, id: (SendPriority, u64), now: Instant) -> bool {
+        self.waiting
+            .range(..id)
+            .any(|(_, (key, rate))| match self.chats.get(key) {
+                Some(sends) => free_at(sends, *rate, now) <= now,
+                None => true,
+            })
+    }
+}
+
+fn expire(sends: &mut VecDeque<Instant>, now: Instant, per: Duration) {
+    while let Some(sent) = sends.front() {
+        if *sent + per <= now {
+            sends.pop_front();
+        } else {
+            break;
+        }
+    }
+}
+
+/// The instant a slot is free in a window of sends, `now` if already free
+fn free_at(sends: &VecDeque<Instant>, rate: Rate, now: Instant) -> Instant {
+    let count = rate.count.max(1) as usize;
+    if sends.len() < count {
+        return now;
+    }
+    let oldest = sends[sends.len() - count];
+    (oldest + rate.per).max(now)
+}
+
+/// One id space for users, small groups and channels (like the Bot API ids)
+fn chat_key(chat: &PackedChat) -> i64 {
+    match chat.ty {
+        PackedType::User | PackedType::Bot => chat.id,
+        PackedType::Chat => -chat.id,
+        _ => -1_000_000_000_000 - chat.id,
+    }
+}
@@ -0,0 +1,93 @@
+use crate::outbound::{OutboundMessage, OutboundStore};
+use crate::session::write_atomic;
+use crate::Result;
+use anyhow::Context;
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+
+/// Store the outbound messages in a file, one message per line.
+///
+/// The file is rewritten atomically on every change, fine for the small queues
+/// of one process, use the SQLite store for large ones.
+pub struct FileOutboundStore {
+    path: PathBuf,
+    messages: Mutex<Option<BTreeMap<u64, OutboundMessage>>>,
+}
+
+impl FileOutboundStore {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            messages: Mutex::new(None),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    async fn read(&self) -> Result<BTreeMap<u64, OutboundMessage>> {
+        let path = self.path.clone();
+        let content = tokio::task::spawn_blocking(move || match std::fs::read_to_string(&path) {
+            Ok(content) => Ok(content),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+            Err(err) => Err(err),
+        })
+        .await??;
+        let mut messages = BTreeMap::new();
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            let message = OutboundMessage::from_bytes(&URL_SAFE_NO_PAD.decode(line.trim())?)
+                .with_context(|| format!("read outbound file {}", self.path.display()))?;
+            messages.insert(message.id, message);
+        }
+        Ok(messages)
+    }
+
+    /// Apply `f` to the messages, then write the file if `f` returned true
+    async fn update<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut BTreeMap<u64, OutboundMessage>) -> (T, bool),
+    {
+        let mut lock = self.messages.lock().await;
+        if lock.is_none() {
+            *lock = Some(self.read().await?);
+        }
+        let messages = lock.as_mut().unwrap();
+        let (value, changed) = f(messages);
+        if changed {
+            let mut content = String::new();
+            for message in messages.values() {
+                content.push_str(&URL_SAFE_NO_PAD.encode(message.to_bytes()));
+                content.push('\n');
+            }
+            let path = self.path.clone();
+            tokio::task::spawn_blocking(move || write_atomic(&path, content.as_bytes())).await??;
+        }
+        Ok(value)
+    }
+}
+
+#[async_trait]
+impl OutboundStore for FileOutboundStore {
+    async fn save_message(&self, message: &OutboundMessage) -> Result<()> {
+        self.update(|messages| {
+            messages.insert(message.id, message.clone());
+            ((), true)
+        })
+        .await
+    }
+
+    async fn remove_message(&self, id: u64) -> Result<()> {
+        self.update(|messages| ((), messages.remove(&id).is_some()))
+            .await
+    }
+
+    async fn load_messages(&self) -> Result<Vec<OutboundMessage>> {
+        self.update(|messages| (messages.values().cloned().collect(), false))
+            .await
+    }
+}
@@ -0,0 +1,382 @@
+mod file;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use file::*;
+#[cfg(feature = "sqlite")]
+pub use sqlite::*;
+
+use crate::{may_have_executed, ClientHandle, Result, SendPriority};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use grammers_client::InvocationError;
+use grammers_session::PackedChat;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{Mutex, Notify};
+use tokio::time::sleep;
+
+const FORMAT_VERSION: u8 = 1;
+
+/// A text message waiting in an `OutboundQueue`
+#[derive(Debug, Clone)]
+pub struct OutboundMessage {
+    pub id: u64,
+    pub chat: PackedChat,
+    pub text: String,
+    /// Failed sends so far
+    pub attempts: u32,
+    /// Unix time of the next send
+    pub next_attempt_at: u64,
+    /// Gave up after `OutboundPolicy::max_attempts`, kept until requeued or discarded
+    pub dead: bool,
+    pub last_error: Option<String>,
+}
+
+impl OutboundMessage {
+    /// The format kept by the stores, `from_bytes` reads it back
+    ///
+    /// ```
+    /// use teleser::re_exports::grammers_session::{PackedChat, PackedType};
+    /// use teleser::OutboundMessage;
+    ///
+    /// let message = OutboundMessage {
+    ///     id: 7,
+    ///     chat: PackedChat {
+    ///         ty: PackedType::Megagroup,
+    ///         id: 42,
+    ///         access_hash: Some(-5),
+    ///     },
+    ///     text: "hé".into(),
+    ///     attempts: 2,
+    ///     next_attempt_at: 1_700_000_000,
+    ///     dead: true,
+    ///     last_error: Some("FLOOD".into()),
+    /// };
+    /// // messages stored by an older build must stay readable
+    /// #[rustfmt::skip]
+    /// let stored = [
+    ///     1, // version
+    ///     7, 0, 0, 0, 0, 0, 0, 0, // id
+    ///     104, 42, 0, 0, 0, 0, 0, 0, 0, 251, 255, 255, 255, 255, 255, 255, 255, // chat
+    ///     2, 0, 0, 0, // attempts
+    ///     0, 241, 83, 101, 0, 0, 0, 0, // next attempt
+    ///     1, // dead
+    ///     3, 0, 0, 0, 104, 195, 169, // text
+    ///     1, 5, 0, 0, 0, 70, 76, 79, 79, 68, // last error
+    /// ];
+    /// assert_eq!(message.to_bytes(), stored);
+    ///
+    /// let read = OutboundMessage::from_bytes(&stored).unwrap();
+    /// assert_eq!((read.id, read.chat, read.text.as_str()), (7, message.chat, "hé"));
+    /// assert_eq!((read.attempts, read.next_attempt_at, read.dead), (2, 1_700_000_000, true));
+    /// assert_eq!(read.last_error.as_deref(), Some("FLOOD"));
+    ///
+    /// // no last error, then truncated data and an unknown version
+    /// let message = OutboundMessage { last_error: None, ..message };
+    /// assert!(OutboundMessage::from_bytes(&message.to_bytes()).unwrap().last_error.is_none());
+    /// assert!(OutboundMessage::from_bytes(&stored[..stored.len() - 1]).is_err());
+    /// assert!(OutboundMessage::from_bytes(&[2]).is_err());
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![FORMAT_VERSION];
+        buf.extend_from_slice(&self.id.to_le_bytes());
+        buf.extend_from_slice(&self.chat.to_bytes());
+        buf.extend_from_slice(&self.attempts.to_le_bytes());
+        buf.extend_from_slice(&self.next_attempt_at.to_le_bytes());
+        buf.push(self.dead as u8);
+        write_str(&mut buf, &self.text);
+        match &self.last_error {
+            Some(error) => {
+                buf.push(1);
+                write_str(&mut buf, error);
+            }
+            None => buf.push(0),
+        }
+        buf
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> Result<Self> {
+        let mut reader = Reader(buf);
+        if reader.take(1)?[0] != FORMAT_VERSION {
+            return Err(anyhow!("unsupported outbound message version"));
+        }
+        let id = u64::from_le_bytes(reader.take(8)?.try_into()?);
+        let chat = PackedChat::from_bytes(reader.take(17)?)
+            .map_err(|_| anyhow!("malformed outbound message chat"))?;
+        let attempts = u32::from_le_bytes(reader.take(4)?.try_into()?);
+        let next_attempt_at = u64::from_le_bytes(reader.take(8)?.try_into()?);
+        let dead = reader.take(1)?[0] != 0;
+        let text = reader.read_str()?;
+        let last_error = match reader.take(1)?[0] {
+            0 => None,
+            _ => Some(reader.read_str()?),
+        };
+        Ok(Self {
+            id,
+            chat,
+            text,
+            attempts,
+            next_attempt_at,
+            dead,
+            last_error,
+        })
+    }
+}
+
+fn write_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(anyhow!("outbound message is truncated"));
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn read_str(&mut self) -> Result<String> {
+        let len = u32::from_le_bytes(self.take(4)?.try_into()?) as usize;
+        Ok(String::from_utf8(self.take(len)?.to_vec())?)
+    }
+}
+
+/// Durable storage of the messages of an `OutboundQueue`
+#[async_trait]
+pub trait OutboundStore {
+    /// Insert or replace the message with the same id
+    async fn save_message(&self, message: &OutboundMessage) -> Result<()>;
+    async fn remove_message(&self, id: u64) -> Result<()>;
+    async fn load_messages(&self) -> Result<Vec<OutboundMessage>>;
+}
+
+/// How an `OutboundQueue` retries failed sends
+#[derive(Debug, Clone)]
+pub struct OutboundPolicy {
+    /// Failed sends before the message goes to the dead letters
+    pub max_attempts: u32,
+    /// Wait after the first failure, doubled on every next one
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    pub priority: SendPriority,
+    /// A send cut off by a lost connection may have been delivered: retry it like other
+    /// failures (false, at least once), or make it a dead letter at once (at most once)
+    pub at_most_once: bool,
+}
+
+impl Default for OutboundPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(60 * 60),
+            priority: SendPriority::Low,
+            at_most_once: false,
+        }
+    }
+}
+
+/// Messages persisted before sending, so they survive a restart.
+///
+/// The client sends them after connecting, and removes a message only once it is sent
+/// (at least once delivery: a crash or a lost connection right after sending sends it
+/// again, unless `OutboundPolicy::at_most_once`).
+/// Failed sends are retried with backoff, then kept as dead letters.
+pub struct OutboundQueue {
+    store: Box<dyn OutboundStore + Send + Sync>,
+    policy: OutboundPolicy,
+    next_id: Mutex<Option<u64>>,
+    changed: Notify,
+}
+
+impl OutboundQueue {
+    pub fn new(store: Box<dyn OutboundStore + Send + Sync>) -> Self {
+        Self {
+            store,
+            policy: OutboundPolicy::default(),
+            next_id: Mutex::new(None),
+            changed: Notify::new(),
+        }
+    }
+
+    pub fn with_policy(mut self, policy: OutboundPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Persist a message to send, returns its id
+    pub async fn enqueue<C: Into<PackedChat>, S: Into<String>>(
+        &self,
+        chat: C,
+        text: S,
+    ) -> Result<u64> {
+        let mut next_id = self.next_id.lock().await;
+        let id = match *next_id {
+            Some(id) => id,
+            None => self
+                .store
+                .load_messages()
+                .await?
+                .iter()
+                .map(|message| message.id + 1)
+                .max()
+                .unwrap_or(1),
+        };
+        self.store
+            .save_message(&OutboundMessage {
+                id,
+                chat: chat.into(),
+                text: text.into(),
+                attempts: 0,
+                next_attempt_at: 0,
+                dead: false,
+                last_error: None,
+            })
+            .await?;
+        *next_id = Some(id + 1);
+        drop(next_id);
+        self.changed.notify_one();
+        Ok(id)
+    }
+
+    /// Messages waiting to be sent
+    pub async fn pending(&self) -> Result<Vec<OutboundMessage>> {
+        self.messages(false).await
+    }
+
+    pub async fn dead_letters(&self) -> Result<Vec<OutboundMessage>> {
+        self.messages(true).await
+    }
+
+    /// Send a dead letter again, with its attempts reset
+    pub async fn requeue(&self, id: u64) -> Result<()> {
+        let mut message = self
+            .messages(true)
+            .await?
+            .into_iter()
+            .find(|message| message.id == id)
+            .ok_or_else(|| anyhow!("dead letter not found : {id}"))?;
+        message.dead = false;
+        message.attempts = 0;
+        message.next_attempt_at = 0;
+        self.store.save_message(&message).await?;
+        self.changed.notify_one();
+        Ok(())
+    }
+
+    /// Remove a message, pending or dead
+    pub async fn discard(&self, id: u64) -> Result<()> {
+        self.store.remove_message(id).await
+    }
+
+    async fn messages(&self, dead: bool) -> Result<Vec<OutboundMessage>> {
+        let mut messages: Vec<_> = self
+            .store
+            .load_messages()
+            .await?
+            .into_iter()
+            .filter(|message| message.dead == dead)
+            .collect();
+        messages.sort_by_key(|message| message.id);
+        Ok(messages)
+    }
+
+    /// Send the due messages through the handle until the client stops
+    pub(crate) async fn drain(&self, handle: ClientHandle) {
+        loop {
+            let wait = match self.send_due(&handle).await {
+                Ok(wait) => wait,
+                Err(err) => {
+                    tracing::error!("outbound queue error : {err:?}");
+                    Some(self.policy.backoff)
+                }
+            };
+            if handle.is_closed() {
+                return;
+            }
+            match wait {
+                Some(wait) => {
+                    tokio::select! {
+                        _ = sleep(wait) => {}
+                        _ = self.changed.notified() => {}
+                    }
+                }
+                None => self.changed.notified().await,
+            }
+        }
+    }
+
+    /// Send the messages due now, returns the wait until the next due one
+    async fn send_due(&self, handle: &ClientHandle) -> Result<Option<Duration>> {
+        for mut message in self.messages(false).await? {
+            let now = unix_now();
+            if message.next_attempt_at > now {
+                continue;
+            }
+            let sent = handle
+                .send_message_with_priority(
+                    message.chat,
+                    message.text.as_str(),
+                    self.policy.priority,
+                )
+                .await;
+            match sent {
+                Ok(_) => self.store.remove_message(message.id).await?,
+                Err(err) => {
+                    // sending again could deliver the message twice
+                    let give_up = self.policy.at_most_once
+                        && err
+                            .downcast_ref::<InvocationError>()
+                            .is_some_and(may_have_executed);
+                    if handle.is_closed() && !give_up {
+                        return Ok(None);
+                    }
+                    message.attempts += 1;
+                    message.last_error = Some(err.to_string());
+                    if give_up {
+                        tracing::error!(
+                            "outbound message {} may have been sent : {err}",
+                            message.id
+                        );
+                        message.dead = true;
+                    } else if message.attempts >= self.policy.max_attempts {
+                        tracing::error!("outbound message {} dead : {err}", message.id);
+                        message.dead = true;
+                    } else {
+                        let backoff = self
+                            .policy
+                            .backoff
+                            .saturating_mul(2_u32.saturating_pow(message.attempts - 1))
+                            .min(self.policy.max_backoff);
+                        tracing::warn!(
+                            "outbound message {} failed : retry in {} sec : {err}",
+                            message.id,
+                            backoff.as_secs()
+                        );
+                        message.next_attempt_at = now + backoff.as_secs();
+                    }
+                    self.store.save_message(&message).await?;
+                }
+            }
+        }
+        let now = unix_now();
+        Ok(self
+            .messages(false)
+            .await?
+            .iter()
+            .map(|message| Duration::from_secs(message.next_attempt_at.saturating_sub(now)))
+            .min())
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
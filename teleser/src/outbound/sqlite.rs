@@ -0,0 +1,83 @@
+use crate::outbound::{OutboundMessage, OutboundStore};
+use crate::{Result, SqliteDatabase};
+use async_trait::async_trait;
+use rusqlite::params;
+
+impl SqliteDatabase {
+    /// The outbound store of an account in this database
+    pub fn outbound_store<S: Into<String>>(&self, account: S) -> Result<SqliteOutboundStore> {
+        self.connection()
+            .lock()
+            .map_err(|_| anyhow::anyhow!("sqlite connection poisoned"))?
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS teleser_outbound (
+                    account TEXT NOT NULL,
+                    id INTEGER NOT NULL,
+                    data BLOB NOT NULL,
+                    PRIMARY KEY (account, id)
+                );",
+            )?;
+        Ok(SqliteOutboundStore {
+            database: self.clone(),
+            account: account.into(),
+        })
+    }
+}
+
+/// Store the outbound messages of one account in a `SqliteDatabase`
+pub struct SqliteOutboundStore {
+    database: SqliteDatabase,
+    account: String,
+}
+
+impl SqliteOutboundStore {
+    pub fn account(&self) -> &str {
+        &self.account
+    }
+}
+
+#[async_trait]
+impl OutboundStore for SqliteOutboundStore {
+    async fn save_message(&self, message: &OutboundMessage) -> Result<()> {
+        let account = self.account.clone();
+        let (id, data) = (message.id as i64, message.to_bytes());
+        self.database
+            .call(move |connection| {
+                connection.execute(
+                    "INSERT OR REPLACE INTO teleser_outbound (account, id, data) VALUES (?1, ?2, ?3)",
+                    params![account, id, data],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn remove_message(&self, id: u64) -> Result<()> {
+        let account = self.account.clone();
+        self.database
+            .call(move |connection| {
+                connection.execute(
+                    "DELETE FROM teleser_outbound WHERE account = ?1 AND id = ?2",
+                    params![account, id as i64],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn load_messages(&self) -> Result<Vec<OutboundMessage>> {
+        let account = self.account.clone();
+        self.database
+            .call(move |connection| {
+                let mut statement = connection
+                    .prepare("SELECT data FROM teleser_outbound WHERE account = ?1 ORDER BY id")?;
+                let rows = statement
+                    .query_map(params![account], |row| row.get::<_, Vec<u8>>(0))?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                rows.iter()
+                    .map(|data| OutboundMessage::from_bytes(data))
+                    .collect()
+            })
+            .await
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{run_client_and_reconnect, Client, ClientExit, ClientHandle, Result};
+use anyhow::anyhow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{Mutex, Notify};
+use tokio::task::JoinHandle;
+
+/// Run many accounts in one process.
+///
+/// Every client keeps its own auth and session store, modules can be shared
+/// by passing the same `Arc<Vec<Module>>` to each `ClientBuilder`.
+/// Clients are keyed by `Client::account`.
+pub struct ClientPool {
+    clients: Mutex<HashMap<String, PoolEntry>>,
+    stopped: Arc<Notify>,
+}
+
+struct PoolEntry {
+    client: Arc<Client>,
+    task: Option<JoinHandle<Result<ClientExit>>>,
+}
+
+impl Default for ClientPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientPool {
+    pub fn new() -> Self {
+        Self {
+            clients: Mutex::new(HashMap::new()),
+            stopped: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Add a client without starting it, the account name must be unique in the pool
+    pub async fn add<S: Into<Arc<Client>>>(&self, client: S) -> Result<()> {
+        let client = client.into();
+        let mut clients = self.clients.lock().await;
+        if clients.contains_key(client.account()) {
+            return Err(anyhow!("account already exists : {}", client.account()));
+        }
+        clients.insert(
+            client.account().to_owned(),
+            PoolEntry { client, task: None },
+        );
+        Ok(())
+    }
+
+    /// Stop and remove a client
+    pub async fn remove(&self, account: &str) -> Result<Option<Arc<Client>>> {
+        self.stop(account).await?;
+        Ok(self
+            .clients
+            .lock()
+            .await
+            .remove(account)
+            .map(|entry| entry.client))
+    }
+
+    pub async fn accounts(&self) -> Vec<String> {
+        self.clients.lock().await.keys().cloned().collect()
+    }
+
+    pub async fn client(&self, account: &str) -> Option<Arc<Client>> {
+        self.clients
+            .lock()
+            .await
+            .get(account)
+            .map(|entry| entry.client.clone())
+    }
+
+    pub async fn handle(&self, account: &str) -> Option<ClientHandle> {
+        self.clients
+            .lock()
+            .await
+            .get(account)
+            .map(|entry| entry.client.handle())
+    }
+
+    /// Whether the client of the account is running
+    pub async fn is_running(&self, account: &str) -> bool {
+        match self.clients.lock().await.get(account) {
+            Some(PoolEntry {
+                task: Some(task), ..
+            }) => !task.is_finished(),
+            _ => false,
+        }
+    }
+
+    /// Spawn `run_client_and_reconnect` for the account, does nothing if it is running
+    pub async fn start(&self, account: &str) -> Result<()> {
+        let mut clients = self.clients.lock().await;
+        let entry = clients
+            .get_mut(account)
+            .ok_or_else(|| anyhow!("account not found : {account}"))?;
+        if let Some(task) = &entry.task {
+            if !task.is_finished() {
+                return Ok(());
+            }
+        }
+        let client = entry.client.clone();
+        let account = account.to_owned();
+        let stopped = self.stopped.clone();
+        entry.task = Some(tokio::spawn(async move {
+            let result = run_client_and_reconnect(client).await;
+            match &result {
+                Ok(ClientExit::LoggedOut) => tracing::error!("client {account} logged out"),
+                Ok(ClientExit::Shutdown) => (),
+                Err(err) => tracing::error!("client {account} stopped : {err:?}"),
+            }
+            stopped.notify_waiters();
+            result
+        }));
+        Ok(())
+    }
+
+    /// Ask the client of the account to stop and wait for it,
+    /// returns how it exited if it was started
+    pub async fn stop(&self, account: &str) -> Result<Option<ClientExit>> {
+        let task = {
+            let mut clients = self.clients.lock().await;
+            let entry = clients
+                .get_mut(account)
+                .ok_or_else(|| anyhow!("account not found : {account}"))?;
+            let task = entry.task.take();
+            // a signal without a running task would stop the next start at once
+            if task.as_ref().is_some_and(|task| !task.is_finished()) {
+                entry.client.shutdown();
+            }
+            task
+        };
+        match task {
+            Some(task) => Ok(Some(task.await??)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn start_all(&self) -> Result<()> {
+        for account in self.accounts().await {
+            self.start(&account).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn stop_all(&self) -> Result<()> {
+        for account in self.accounts().await {
+            if let Err(err) = self.stop(&account).await {
+                tracing::error!("client {account} stopped : {err:?}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Start every client and wait until all of them stopped (ctrl-c stops all)
+    pub async fn run(&self) -> Result<()> {
+        self.start_all().await?;
+        loop {
+            let stopped = self.stopped.notified();
+            tokio::pin!(stopped);
+            stopped.as_mut().enable();
+            let running = self
+                .clients
+                .lock()
+                .await
+                .values()
+                .any(|entry| entry.task.as_ref().is_some_and(|t| !t.is_finished()));
+            if !running {
+                return Ok(());
+            }
+            stopped.await;
+        }
+    }
+}
@@ -18,6 +18,11 @@ pub mod grammers_tl_types {
     pub use grammers_tl_types::*;
 }
 
+#[cfg(feature = "sqlite")]
+pub mod rusqlite {
+    pub use rusqlite::*;
+}
+
 pub mod tokio {
     pub use tokio::*;
 }
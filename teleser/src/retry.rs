@@ -0,0 +1,125 @@
+use grammers_client::InvocationError;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How the teleser helpers retry failed requests.
+///
+/// Flood waits up to `InitParams::flood_sleep_threshold` (one minute by default)
+/// are slept by grammers itself, the policy handles the longer ones.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Sleep and retry on a flood wait up to this long, longer waits return the error
+    pub flood_sleep_threshold: Duration,
+    /// Report flood waits at least this long to the error handler
+    pub report_flood_wait: Duration,
+    /// Retries of transient errors (internal server errors, dropped connections)
+    pub max_retries: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            flood_sleep_threshold: Duration::from_secs(5 * 60),
+            report_flood_wait: Duration::from_secs(60),
+            max_retries: 3,
+        }
+    }
+}
+
+/// Errors reported to the error handler of the client
+#[derive(Debug, Clone)]
+pub enum ClientError {
+    /// A request got a flood wait (`FLOOD_WAIT`, `SLOWMODE_WAIT`...),
+    /// `retry` tells if it sleeps and retries or returns the error
+    FloodWait {
+        account: String,
+        name: String,
+        wait: Duration,
+        retry: bool,
+    },
+}
+
+/// Called with the errors of the client, like long flood waits
+pub type ErrorHandler = Box<dyn Fn(&ClientError) + Send + Sync>;
+
+pub(crate) struct Retry {
+    pub(crate) account: Arc<str>,
+    pub(crate) policy: RetryPolicy,
+    pub(crate) error_handler: Option<ErrorHandler>,
+}
+
+impl Retry {
+    pub(crate) fn report(&self, error: &ClientError) {
+        match &self.error_handler {
+            Some(error_handler) => error_handler(error),
+            None => tracing::warn!("{error:?}"),
+        }
+    }
+
+    /// How long to sleep before retrying the failed request, `None` to return the error.
+    /// Requests not `idempotent` are not retried when they may have reached Telegram.
+    pub(crate) fn retry_after(
+        &self,
+        err: &InvocationError,
+        retries: &mut u32,
+        idempotent: bool,
+    ) -> Option<Duration> {
+        if let Some((name, wait)) = flood_wait(err) {
+            let retry = wait <= self.policy.flood_sleep_threshold;
+            if wait >= self.policy.report_flood_wait || !retry {
+                self.report(&ClientError::FloodWait {
+                    account: self.account.to_string(),
+                    name: name.to_owned(),
+                    wait,
+                    retry,
+                });
+            }
+            if retry {
+                tracing::debug!("{name} : sleep {} sec", wait.as_secs());
+                return Some(wait);
+            }
+            return None;
+        }
+        if !idempotent && may_have_executed(err) {
+            return None;
+        }
+        if is_transient(err) && *retries < self.policy.max_retries {
+            *retries += 1;
+            let sleep_sec = 2_u64.pow(*retries - 1);
+            tracing::warn!("transient error : retry in {sleep_sec} sec : {err}");
+            return Some(Duration::from_secs(sleep_sec));
+        }
+        None
+    }
+}
+
+/// The error name and the wait of a flood wait error
+pub fn flood_wait(err: &InvocationError) -> Option<(&str, Duration)> {
+    match err {
+        InvocationError::Rpc(rpc) if rpc.code == 420 || rpc.is("*_WAIT") => rpc
+            .value
+            .map(|value| (rpc.name.as_str(), Duration::from_secs(value as u64))),
+        _ => None,
+    }
+}
+
+/// Whether the request may succeed if sent again as is
+pub fn is_transient(err: &InvocationError) -> bool {
+    match err {
+        InvocationError::Rpc(rpc) => {
+            rpc.code == 500
+                || rpc.code == -503
+                || rpc.is("TIMEOUT")
+                || rpc.is("RPC_CALL_FAIL")
+                || rpc.is("RPC_MCGET_FAIL")
+                || rpc.is("INTERDC_*")
+        }
+        InvocationError::Dropped | InvocationError::Read(_) => true,
+    }
+}
+
+/// Whether the request may have been executed although it failed:
+/// the connection was lost after sending, so no answer came back
+pub fn may_have_executed(err: &InvocationError) -> bool {
+    matches!(err, InvocationError::Dropped | InvocationError::Read(_))
+}
@@ -0,0 +1,104 @@
+use crate::handle::CURRENT_HANDLE;
+use crate::handler::{Module, Process};
+use crate::ClientHandle;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::time::{interval_at, Instant, Interval, MissedTickBehavior};
+
+/// When a `ScheduledProcess` runs
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    /// Every period, the first run one period after the start.
+    /// The period does not drift by the run time, runs missed while a job runs are skipped.
+    Interval(Duration),
+    /// On the times of a cron expression, in UTC
+    #[cfg(feature = "cron")]
+    Cron(Box<cron::Schedule>),
+}
+
+impl Schedule {
+    pub fn interval(period: Duration) -> Self {
+        Self::Interval(period)
+    }
+
+    /// Parse a cron expression with seconds, like `0 30 9 * * Mon-Fri`
+    #[cfg(feature = "cron")]
+    pub fn cron(expression: &str) -> crate::Result<Self> {
+        Ok(Self::Cron(Box::new(expression.parse()?)))
+    }
+
+    /// Wait until the next run, false if it never runs again
+    async fn tick(&self, interval: &mut Option<Interval>) -> bool {
+        match self {
+            Self::Interval(period) => {
+                let interval = interval.get_or_insert_with(|| {
+                    let mut interval = interval_at(Instant::now() + *period, *period);
+                    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                    interval
+                });
+                interval.tick().await;
+                true
+            }
+            #[cfg(feature = "cron")]
+            Self::Cron(schedule) => match schedule.upcoming(chrono::Utc).next() {
+                Some(next) => {
+                    let wait = (next - chrono::Utc::now()).to_std().unwrap_or_default();
+                    tokio::time::sleep(wait).await;
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+}
+
+/// Run the scheduled processes of the modules until the task is aborted.
+///
+/// A run due while the client is disconnected is skipped,
+/// the schedule goes on once the client is ready again.
+pub(crate) async fn run_scheduler(modules: Arc<Vec<Module>>, handle: ClientHandle) {
+    let mut jobs = JoinSet::new();
+    for (m, module) in modules.iter().enumerate() {
+        for (h, handler) in module.handlers.iter().enumerate() {
+            if let Process::ScheduledProcess(_) = &handler.process {
+                jobs.spawn(run_job(modules.clone(), m, h, handle.clone()));
+            }
+        }
+    }
+    while jobs.join_next().await.is_some() {}
+}
+
+async fn run_job(modules: Arc<Vec<Module>>, m: usize, h: usize, handle: ClientHandle) {
+    let module = &modules[m];
+    let handler = &module.handlers[h];
+    let Process::ScheduledProcess(process) = &handler.process else {
+        return;
+    };
+    let schedule = process.schedule();
+    // started after the authorization
+    if handle.client().await.is_err() {
+        return;
+    }
+    let mut interval = None;
+    while schedule.tick(&mut interval).await {
+        if !handle.is_ready() {
+            tracing::debug!(
+                "skip scheduled : {} : {} : not connected",
+                module.id,
+                handler.id
+            );
+            if handle.client().await.is_err() {
+                return;
+            }
+            continue;
+        }
+        tracing::debug!("scheduled : {} : {}", module.id, handler.id);
+        if let Err(err) = CURRENT_HANDLE
+            .scope(handle.clone(), process.handle(handle.clone()))
+            .await
+        {
+            tracing::error!("scheduled {} : {} error : {err:?}", module.id, handler.id);
+        }
+    }
+}
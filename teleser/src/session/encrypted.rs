@@ -0,0 +1,219 @@
+use crate::session::SessionStore;
+use crate::Result;
+use anyhow::{anyhow, Context};
+use argon2::Argon2;
+use async_trait::async_trait;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::path::Path;
+
+const MAGIC: &[u8] = b"TSE\x01";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Secret used to derive the cipher key of an `EncryptedSessionStore`
+#[derive(Clone)]
+pub struct SessionKey {
+    secret: Vec<u8>,
+}
+
+impl SessionKey {
+    pub fn from_passphrase<S: AsRef<str>>(passphrase: S) -> Self {
+        Self {
+            secret: passphrase.as_ref().as_bytes().to_vec(),
+        }
+    }
+
+    /// Use the whole content of a file as the secret
+    pub fn from_key_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let secret =
+            std::fs::read(path).with_context(|| format!("read key file {}", path.display()))?;
+        if secret.is_empty() {
+            return Err(anyhow!("key file {} is empty", path.display()));
+        }
+        Ok(Self { secret })
+    }
+
+    fn cipher(&self, salt: &[u8]) -> Result<ChaCha20Poly1305> {
+        let mut key = Key::default();
+        Argon2::default()
+            .hash_password_into(&self.secret, salt, &mut key)
+            .map_err(|err| anyhow!("derive session key : {err}"))?;
+        Ok(ChaCha20Poly1305::new(&key))
+    }
+}
+
+/// Encrypt the session with an authenticated cipher before passing it to another store.
+///
+/// Data is `magic | salt | nonce | ciphertext`, the key is derived from the
+/// `SessionKey` and the random salt with Argon2id, and ChaCha20-Poly1305 rejects
+/// tampered data. For key rotation, add the previous keys with `with_old_keys`:
+/// they are tried on load, and the next save (or `rotate`) uses the new key.
+///
+/// ```
+/// # teleser::re_exports::tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// use teleser::{EncryptedSessionStore, MemorySessionStore, SessionKey, SessionStore};
+///
+/// async fn stored(data: &[u8]) -> MemorySessionStore {
+///     let store = MemorySessionStore::new();
+///     store.on_save_session(data.to_vec()).await.unwrap();
+///     store
+/// }
+/// let (old_key, new_key) = (SessionKey::from_passphrase("old"), SessionKey::from_passphrase("new"));
+///
+/// // round trip, the inner store only sees the ciphertext
+/// let store = EncryptedSessionStore::new(MemorySessionStore::new(), old_key.clone());
+/// store.on_save_session(b"session".to_vec()).await.unwrap();
+/// let encrypted = store.inner().on_load_session().await.unwrap().unwrap();
+/// assert!(!encrypted.windows(7).any(|window| window == b"session"));
+/// assert_eq!(store.on_load_session().await.unwrap().unwrap(), b"session");
+///
+/// // a flipped byte of the ciphertext is rejected
+/// let mut tampered = encrypted.clone();
+/// *tampered.last_mut().unwrap() ^= 1;
+/// let store = EncryptedSessionStore::new(stored(&tampered).await, old_key.clone());
+/// assert!(store.on_load_session().await.is_err());
+///
+/// // a new key reads the session of the old key only with `with_old_keys`,
+/// // and `rotate` encrypts it with the new key
+/// let store = EncryptedSessionStore::new(stored(&encrypted).await, new_key.clone());
+/// assert!(store.on_load_session().await.is_err());
+/// let store = store.with_old_keys(vec![old_key.clone()]);
+/// assert_eq!(store.on_load_session().await.unwrap().unwrap(), b"session");
+/// store.rotate().await.unwrap();
+/// let rotated = store.inner().on_load_session().await.unwrap().unwrap();
+/// let store = EncryptedSessionStore::new(stored(&rotated).await, new_key.clone());
+/// assert_eq!(store.on_load_session().await.unwrap().unwrap(), b"session");
+///
+/// // a not encrypted session is only read with `with_plaintext_migration`
+/// let store = EncryptedSessionStore::new(stored(b"plain").await, new_key.clone());
+/// assert!(store.on_load_session().await.is_err());
+/// let store = store.with_plaintext_migration(true);
+/// assert_eq!(store.on_load_session().await.unwrap().unwrap(), b"plain");
+/// # });
+/// ```
+pub struct EncryptedSessionStore<S: SessionStore> {
+    inner: S,
+    key: SessionKey,
+    old_keys: Vec<SessionKey>,
+    plaintext_migration: bool,
+}
+
+impl<S: SessionStore + Sync + Send> EncryptedSessionStore<S> {
+    pub fn new(inner: S, key: SessionKey) -> Self {
+        Self {
+            inner,
+            key,
+            old_keys: vec![],
+            plaintext_migration: false,
+        }
+    }
+
+    /// Keys tried after the current key when loading
+    pub fn with_old_keys(mut self, old_keys: Vec<SessionKey>) -> Self {
+        self.old_keys = old_keys;
+        self
+    }
+
+    /// Accept a not encrypted session on load, it is encrypted on the next save
+    pub fn with_plaintext_migration(mut self, plaintext_migration: bool) -> Self {
+        self.plaintext_migration = plaintext_migration;
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Re-encrypt the stored session with the current key
+    pub async fn rotate(&self) -> Result<()> {
+        if let Some(data) = self.on_load_session().await? {
+            self.on_save_session(data).await?;
+        }
+        Ok(())
+    }
+}
+
+fn encrypt(key: &SessionKey, data: &[u8]) -> Result<Vec<u8>> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = key
+        .cipher(&salt)?
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: data,
+                aad: MAGIC,
+            },
+        )
+        .map_err(|_| anyhow!("encrypt session failed"))?;
+    let mut out = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + ciphertext.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+fn decrypt(keys: &[SessionKey], plaintext_migration: bool, data: &[u8]) -> Result<Vec<u8>> {
+    if !data.starts_with(MAGIC) {
+        return if plaintext_migration {
+            tracing::warn!("session is not encrypted, it will be encrypted on next save");
+            Ok(data.to_vec())
+        } else {
+            Err(anyhow!("session is not encrypted"))
+        };
+    }
+    let header = MAGIC.len() + SALT_LEN + NONCE_LEN;
+    if data.len() < header {
+        return Err(anyhow!("encrypted session is truncated"));
+    }
+    let salt = &data[MAGIC.len()..MAGIC.len() + SALT_LEN];
+    let nonce = Nonce::from_slice(&data[MAGIC.len() + SALT_LEN..header]);
+    for key in keys {
+        let payload = Payload {
+            msg: &data[header..],
+            aad: MAGIC,
+        };
+        if let Ok(plain) = key.cipher(salt)?.decrypt(nonce, payload) {
+            return Ok(plain);
+        }
+    }
+    Err(anyhow!(
+        "decrypt session failed : wrong key or the data was tampered"
+    ))
+}
+
+#[async_trait]
+impl<S: SessionStore + Sync + Send> SessionStore for EncryptedSessionStore<S> {
+    async fn on_save_session(&self, data: Vec<u8>) -> Result<()> {
+        // deriving the key with Argon2 takes a while, keep it off the runtime
+        let key = self.key.clone();
+        let data = tokio::task::spawn_blocking(move || encrypt(&key, &data)).await??;
+        self.inner.on_save_session(data).await
+    }
+
+    async fn on_load_session(&self) -> Result<Option<Vec<u8>>> {
+        match self.inner.on_load_session().await? {
+            Some(data) => {
+                let keys: Vec<SessionKey> = std::iter::once(&self.key)
+                    .chain(self.old_keys.iter())
+                    .cloned()
+                    .collect();
+                let plaintext_migration = self.plaintext_migration;
+                let data =
+                    tokio::task::spawn_blocking(move || decrypt(&keys, plaintext_migration, &data))
+                        .await??;
+                Ok(Some(data))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn on_clear_session(&self) -> Result<()> {
+        self.inner.on_clear_session().await
+    }
+}
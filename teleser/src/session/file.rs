@@ -0,0 +1,171 @@
+use crate::session::SessionStore;
+use crate::Result;
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use fs4::{FileExt, TryLockError};
+use grammers_session::Session;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Store the session in a file.
+///
+/// Saving writes a temporary file and renames it over the session, so a crash
+/// never leaves a half written session. The previous session is kept as `<path>.bak`
+/// and loaded if the session is missing or corrupt, and `<path>.lock` is locked
+/// on first use, so a second process using the same path fails fast instead of
+/// sharing the auth key.
+pub struct FileSessionStore {
+    pub path: String,
+}
+
+/// The lock files held by this process, by session path
+static LOCKS: Mutex<BTreeMap<PathBuf, File>> = Mutex::new(BTreeMap::new());
+
+impl FileSessionStore {
+    pub fn new<S: Into<String>>(path: S) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.to_path_buf().into_os_string();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+fn ensure_locked(path: &Path) -> Result<()> {
+    let mut locks = LOCKS.lock().unwrap();
+    if locks.contains_key(path) {
+        return Ok(());
+    }
+    let lock_path = sibling(path, "lock");
+    let file = restrictive_options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .with_context(|| format!("open lock file {}", lock_path.display()))?;
+    // `File::try_lock` of std needs a newer Rust
+    match FileExt::try_lock(&file) {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            return Err(anyhow!(
+                "session {} is used by another process",
+                path.display()
+            ))
+        }
+        Err(TryLockError::Error(err)) => return Err(err.into()),
+    }
+    locks.insert(path.to_path_buf(), file);
+    Ok(())
+}
+
+fn load_blocking(path: &Path) -> Result<Option<Vec<u8>>> {
+    ensure_locked(path)?;
+    let backup = sibling(path, "bak");
+    if path.exists() {
+        let data = std::fs::read(path)?;
+        // an encrypted session never parses here, then neither does its backup
+        if Session::load(&data).is_err() && backup.exists() {
+            let backup_data = std::fs::read(&backup)?;
+            if Session::load(&backup_data).is_ok() {
+                tracing::warn!("session is corrupt, load backup {}", backup.display());
+                return Ok(Some(backup_data));
+            }
+        }
+        return Ok(Some(data));
+    }
+    if backup.exists() {
+        tracing::warn!("session not found, load backup {}", backup.display());
+        return Ok(Some(std::fs::read(backup)?));
+    }
+    Ok(None)
+}
+
+fn save_blocking(path: &Path, data: &[u8]) -> Result<()> {
+    ensure_locked(path)?;
+    if path.exists() {
+        write_atomic(&sibling(path, "bak"), &std::fs::read(path)?)?;
+    }
+    write_atomic(path, data)
+}
+
+fn clear_blocking(path: &Path) -> Result<()> {
+    ensure_locked(path)?;
+    // the backup holds the same revoked auth key
+    for path in [path.to_path_buf(), sibling(path, "bak")] {
+        match std::fs::remove_file(&path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => (),
+        }
+    }
+    sync_parent(path)
+}
+
+/// Write a temporary file readable by the owner only, then rename it over `path`
+pub(crate) fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let mut tmp = path.to_path_buf().into_os_string();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut file = restrictive_options()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&tmp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&tmp, path)?;
+    sync_parent(path)
+}
+
+#[cfg(unix)]
+fn restrictive_options() -> OpenOptions {
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut options = OpenOptions::new();
+    options.mode(0o600);
+    options
+}
+
+#[cfg(not(unix))]
+fn restrictive_options() -> OpenOptions {
+    OpenOptions::new()
+}
+
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[async_trait]
+impl SessionStore for FileSessionStore {
+    async fn on_save_session(&self, data: Vec<u8>) -> Result<()> {
+        let path = PathBuf::from(&self.path);
+        tokio::task::spawn_blocking(move || save_blocking(&path, &data)).await?
+    }
+
+    async fn on_load_session(&self) -> Result<Option<Vec<u8>>> {
+        let path = PathBuf::from(&self.path);
+        tokio::task::spawn_blocking(move || load_blocking(&path)).await?
+    }
+
+    async fn on_clear_session(&self) -> Result<()> {
+        let path = PathBuf::from(&self.path);
+        tokio::task::spawn_blocking(move || clear_blocking(&path)).await?
+    }
+}
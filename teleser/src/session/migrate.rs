@@ -0,0 +1,185 @@
+use crate::Result;
+use anyhow::{anyhow, Context};
+use base64::alphabet::URL_SAFE;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
+use grammers_session::Session;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+const LENIENT_URL_SAFE: GeneralPurpose = GeneralPurpose::new(
+    &URL_SAFE,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+const PRODUCTION_DCS: [(i32, Ipv4Addr); 5] = [
+    (1, Ipv4Addr::new(149, 154, 175, 53)),
+    (2, Ipv4Addr::new(149, 154, 167, 51)),
+    (3, Ipv4Addr::new(149, 154, 175, 100)),
+    (4, Ipv4Addr::new(149, 154, 167, 91)),
+    (5, Ipv4Addr::new(91, 108, 56, 130)),
+];
+
+const TEST_DCS: [(i32, Ipv4Addr); 3] = [
+    (1, Ipv4Addr::new(149, 154, 175, 10)),
+    (2, Ipv4Addr::new(149, 154, 167, 40)),
+    (3, Ipv4Addr::new(149, 154, 175, 117)),
+];
+
+/// A session exported by another Telegram library
+pub struct ForeignSession {
+    pub dc_id: i32,
+    pub server_addr: SocketAddr,
+    pub auth_key: [u8; 256],
+    /// Pyrogram only, Telethon string sessions have no user
+    pub user_id: Option<i64>,
+    pub bot: Option<bool>,
+}
+
+impl ForeignSession {
+    /// Build a session usable by `Client`.
+    ///
+    /// Without a user id, a placeholder user keeps the data center,
+    /// teleser replaces it with the real user after connecting.
+    pub fn into_session(self) -> Session {
+        let session = Session::new();
+        session.insert_dc(self.dc_id, self.server_addr, self.auth_key);
+        session.set_user(
+            self.user_id.unwrap_or_default(),
+            self.dc_id,
+            self.bot.unwrap_or_default(),
+        );
+        session
+    }
+}
+
+/// Parse a Telethon `StringSession`.
+///
+/// Sessions of the test data centers are rejected, grammers always connects
+/// to the production data center of the id.
+///
+/// ```
+/// // StringSession.save() of Telethon 1.x, data center 2
+/// let s = "1ApWapzMBu4bdV3huSYQuXIdvujzuDpQnpsJNxGtAM6b6JeMeRTi515D7aMH62MFjCnS3K041wkSI5UMAhH6I1j3DPqiV2qJ5WQXQVtc4vOetkH83CqtaBph2auIN_4Y2bE66KlObnXCHl_ZKLtc3e049T2SQOIsYQz20pYSKVEsr9IQYth0TBmo6u3dfPcYR7On_pH_63YRw1TSE43CwvsipwJ_BSbrwyvF32Da37ExPg7XTed3UiEc133qWC6cbq_slvXoXiMCtbTYA7hHeg_ZyaSLtrPoQ4PgOHOBLxcs4dVmAl_Cg4F-kahoARHeifcofj1yuuVlAVr5qRJMjxJdHw032vVM=";
+/// let session = teleser::parse_telethon_session(s).unwrap();
+/// assert_eq!(session.dc_id, 2);
+/// assert_eq!(session.server_addr.to_string(), "149.154.167.51:443");
+/// assert_eq!(session.auth_key[..2], [0x86, 0xdd]);
+/// assert_eq!(session.auth_key[255], 83);
+/// assert_eq!(session.user_id, None);
+///
+/// // the same key on the test data center 149.154.167.40:80
+/// let test_dc = "1ApWapygAUIbdV3huSYQuXIdvujzuDpQnpsJNxGtAM6b6JeMeRTi515D7aMH62MFjCnS3K041wkSI5UMAhH6I1j3DPqiV2qJ5WQXQVtc4vOetkH83CqtaBph2auIN_4Y2bE66KlObnXCHl_ZKLtc3e049T2SQOIsYQz20pYSKVEsr9IQYth0TBmo6u3dfPcYR7On_pH_63YRw1TSE43CwvsipwJ_BSbrwyvF32Da37ExPg7XTed3UiEc133qWC6cbq_slvXoXiMCtbTYA7hHeg_ZyaSLtrPoQ4PgOHOBLxcs4dVmAl_Cg4F-kahoARHeifcofj1yuuVlAVr5qRJMjxJdHw032vVM=";
+/// assert!(teleser::parse_telethon_session(test_dc).is_err());
+///
+/// // data center 7 does not exist
+/// let unknown_dc = "1B5WapzMBu4bdV3huSYQuXIdvujzuDpQnpsJNxGtAM6b6JeMeRTi515D7aMH62MFjCnS3K041wkSI5UMAhH6I1j3DPqiV2qJ5WQXQVtc4vOetkH83CqtaBph2auIN_4Y2bE66KlObnXCHl_ZKLtc3e049T2SQOIsYQz20pYSKVEsr9IQYth0TBmo6u3dfPcYR7On_pH_63YRw1TSE43CwvsipwJ_BSbrwyvF32Da37ExPg7XTed3UiEc133qWC6cbq_slvXoXiMCtbTYA7hHeg_ZyaSLtrPoQ4PgOHOBLxcs4dVmAl_Cg4F-kahoARHeifcofj1yuuVlAVr5qRJMjxJdHw032vVM=";
+/// assert!(teleser::parse_telethon_session(unknown_dc).is_err());
+/// ```
+pub fn parse_telethon_session(string_session: &str) -> Result<ForeignSession> {
+    let string_session = string_session.trim();
+    let encoded = string_session
+        .strip_prefix('1')
+        .ok_or_else(|| anyhow!("unsupported telethon string session version"))?;
+    let data = LENIENT_URL_SAFE
+        .decode(encoded)
+        .context("malformed telethon string session")?;
+    let ip: IpAddr = match data.len() {
+        263 => Ipv4Addr::from(<[u8; 4]>::try_from(&data[1..5])?).into(),
+        275 => Ipv6Addr::from(<[u8; 16]>::try_from(&data[1..17])?).into(),
+        len => return Err(anyhow!("telethon string session has wrong length {len}")),
+    };
+    if TEST_DCS
+        .iter()
+        .any(|(_, test_ip)| ip == IpAddr::from(*test_ip))
+    {
+        return Err(anyhow!(
+            "sessions of the test data centers are not supported"
+        ));
+    }
+    let dc_id = data[0] as i32;
+    check_dc_id(dc_id)?;
+    let rest = &data[data.len() - 258..];
+    Ok(ForeignSession {
+        dc_id,
+        server_addr: SocketAddr::new(ip, u16::from_be_bytes([rest[0], rest[1]])),
+        auth_key: rest[2..].try_into()?,
+        user_id: None,
+        bot: None,
+    })
+}
+
+/// Parse a Pyrogram session string (all of the 1.x and 2.x formats).
+///
+/// Sessions of `test_mode` are rejected, grammers always connects
+/// to the production data center of the id.
+///
+/// ```
+/// // export_session_string() of Pyrogram 2.x, data center 5
+/// let s = "BQAAB_gAht1XeG5JhC5ch2-6PO4OlCemwk3Ea0Azpvol4x5FOLnXkPtowfrYwWMKdLcrTjXCRIjlQwCEfojWPcM-qJXaonlZBdBW1zi8562QfzcKq1oGmHZq4g3_hjZsTroqU5udcIeX9kou1zd7Tj1PZJA4ixhDPbSlhIpUSyv0hBi2HRMGajq7d189xhHs6f-kf_rdhHDVNITjcLC-yKnAn8FJuvDK8XfYNrfsTE-DtdN53dSIRzXfepYLpxur-yW9eheIwK1tNgDuEd6D9nJpIu2s-hDg-A4c4EvFyzh1WYCX8KDgX6RqGgBEd6J9yh-PXK65WUBWvmpEkyPEl0fDTfa9UwAAAAFDyE3SAA";
+/// let session = teleser::parse_pyrogram_session(s).unwrap();
+/// assert_eq!(session.dc_id, 5);
+/// assert_eq!(session.server_addr.to_string(), "91.108.56.130:443");
+/// assert_eq!(session.auth_key[..2], [0x86, 0xdd]);
+/// assert_eq!(session.auth_key[255], 83);
+/// assert_eq!(session.user_id, Some(5432167890));
+/// assert_eq!(session.bot, Some(false));
+///
+/// // the same account in test_mode
+/// let test_mode = "AgAAB_gBht1XeG5JhC5ch2-6PO4OlCemwk3Ea0Azpvol4x5FOLnXkPtowfrYwWMKdLcrTjXCRIjlQwCEfojWPcM-qJXaonlZBdBW1zi8562QfzcKq1oGmHZq4g3_hjZsTroqU5udcIeX9kou1zd7Tj1PZJA4ixhDPbSlhIpUSyv0hBi2HRMGajq7d189xhHs6f-kf_rdhHDVNITjcLC-yKnAn8FJuvDK8XfYNrfsTE-DtdN53dSIRzXfepYLpxur-yW9eheIwK1tNgDuEd6D9nJpIu2s-hDg-A4c4EvFyzh1WYCX8KDgX6RqGgBEd6J9yh-PXK65WUBWvmpEkyPEl0fDTfa9UwAAAAFDyE3SAA";
+/// assert!(teleser::parse_pyrogram_session(test_mode).is_err());
+/// ```
+pub fn parse_pyrogram_session(string_session: &str) -> Result<ForeignSession> {
+    let data = LENIENT_URL_SAFE
+        .decode(string_session.trim())
+        .context("malformed pyrogram session string")?;
+    // (offset of test mode, offset of auth key, user id size)
+    let (test_mode, auth_key, user_id_size) = match data.len() {
+        // ">BI?256sQ?" dc id, api id, test mode, auth key, user id, is bot
+        271 => (5, 6, 8),
+        // ">B?256sQ?"
+        267 => (1, 2, 8),
+        // ">B?256sI?"
+        263 => (1, 2, 4),
+        len => return Err(anyhow!("pyrogram session string has wrong length {len}")),
+    };
+    if data[test_mode] != 0 {
+        return Err(anyhow!(
+            "sessions of the test data centers are not supported"
+        ));
+    }
+    let dc_id = data[0] as i32;
+    check_dc_id(dc_id)?;
+    let user_id_offset = auth_key + 256;
+    let user_id = match user_id_size {
+        8 => i64::from_be_bytes(data[user_id_offset..user_id_offset + 8].try_into()?),
+        _ => u32::from_be_bytes(data[user_id_offset..user_id_offset + 4].try_into()?) as i64,
+    };
+    let ip = PRODUCTION_DCS
+        .iter()
+        .find(|(id, _)| *id == dc_id)
+        .map(|(_, ip)| *ip)
+        .ok_or_else(|| anyhow!("unknown data center {dc_id}"))?;
+    Ok(ForeignSession {
+        dc_id,
+        server_addr: SocketAddr::new(ip.into(), 443),
+        auth_key: data[auth_key..auth_key + 256].try_into()?,
+        user_id: Some(user_id),
+        bot: Some(data[user_id_offset + user_id_size] != 0),
+    })
+}
+
+/// grammers indexes its own address table with the id, only 1 to 5 exist
+fn check_dc_id(dc_id: i32) -> Result<()> {
+    if !(1..=5).contains(&dc_id) {
+        return Err(anyhow!("unknown data center {dc_id}"));
+    }
+    Ok(())
+}
+
+pub fn import_telethon_session(string_session: &str) -> Result<Session> {
+    Ok(parse_telethon_session(string_session)?.into_session())
+}
+
+pub fn import_pyrogram_session(string_session: &str) -> Result<Session> {
+    Ok(parse_pyrogram_session(string_session)?.into_session())
+}
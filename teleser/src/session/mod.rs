@@ -0,0 +1,30 @@
+#[cfg(feature = "encrypt")]
+mod encrypted;
+mod file;
+mod migrate;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod string;
+
+#[cfg(feature = "encrypt")]
+pub use encrypted::*;
+pub(crate) use file::write_atomic;
+pub use file::*;
+pub use migrate::*;
+#[cfg(feature = "sqlite")]
+pub use sqlite::*;
+pub use string::*;
+
+use crate::Result;
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait SessionStore {
+    async fn on_save_session(&self, data: Vec<u8>) -> Result<()>;
+    async fn on_load_session(&self) -> Result<Option<Vec<u8>>>;
+
+    /// Called when the session is logged out, the next load should return none
+    async fn on_clear_session(&self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use crate::session::SessionStore;
+use crate::Result;
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// A SQLite database shared by the stores of many accounts.
+///
+/// Cloning shares the same connection, so one database file can hold the
+/// sessions of a whole fleet (and other teleser state).
+#[derive(Clone)]
+pub struct SqliteDatabase {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteDatabase {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "synchronous", "FULL")?;
+        Self::from_connection(connection)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    pub fn from_connection(connection: Connection) -> Result<Self> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS teleser_sessions (
+                account TEXT PRIMARY KEY NOT NULL,
+                data BLOB NOT NULL,
+                updated_at INTEGER NOT NULL
+            );",
+        )?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    pub(crate) fn connection(&self) -> &Mutex<Connection> {
+        &self.connection
+    }
+
+    /// Run a blocking closure with the connection off the async runtime
+    pub async fn call<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection
+                .lock()
+                .map_err(|_| anyhow::anyhow!("sqlite connection poisoned"))?;
+            f(&mut connection)
+        })
+        .await?
+    }
+
+    /// The session store of an account in this database
+    pub fn session_store<S: Into<String>>(&self, account: S) -> SqliteSessionStore {
+        SqliteSessionStore {
+            database: self.clone(),
+            account: account.into(),
+        }
+    }
+
+    pub async fn accounts(&self) -> Result<Vec<String>> {
+        self.call(|connection| {
+            let mut statement =
+                connection.prepare("SELECT account FROM teleser_sessions ORDER BY account")?;
+            let accounts = statement
+                .query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            Ok(accounts)
+        })
+        .await
+    }
+}
+
+/// Store the session of one account in a `SqliteDatabase`
+pub struct SqliteSessionStore {
+    database: SqliteDatabase,
+    account: String,
+}
+
+impl SqliteSessionStore {
+    /// Open the database file and use the session of the account
+    pub fn open<P: AsRef<Path>, S: Into<String>>(path: P, account: S) -> Result<Self> {
+        Ok(SqliteDatabase::open(path)?.session_store(account))
+    }
+
+    pub fn account(&self) -> &str {
+        &self.account
+    }
+
+    pub fn database(&self) -> &SqliteDatabase {
+        &self.database
+    }
+
+    /// Delete the stored session of the account
+    pub async fn delete_session(&self) -> Result<()> {
+        let account = self.account.clone();
+        self.database
+            .call(move |connection| {
+                connection.execute(
+                    "DELETE FROM teleser_sessions WHERE account = ?1",
+                    params![account],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl SessionStore for SqliteSessionStore {
+    async fn on_save_session(&self, data: Vec<u8>) -> Result<()> {
+        let account = self.account.clone();
+        self.database
+            .call(move |connection| {
+                connection.execute(
+                    "INSERT INTO teleser_sessions (account, data, updated_at)
+                    VALUES (?1, ?2, strftime('%s', 'now'))
+                    ON CONFLICT (account) DO UPDATE SET
+                    data = excluded.data, updated_at = excluded.updated_at",
+                    params![account, data],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn on_load_session(&self) -> Result<Option<Vec<u8>>> {
+        let account = self.account.clone();
+        self.database
+            .call(move |connection| {
+                Ok(connection
+                    .query_row(
+                        "SELECT data FROM teleser_sessions WHERE account = ?1",
+                        params![account],
+                        |row| row.get(0),
+                    )
+                    .optional()?)
+            })
+            .await
+    }
+
+    async fn on_clear_session(&self) -> Result<()> {
+        self.delete_session().await
+    }
+}
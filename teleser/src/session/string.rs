@@ -0,0 +1,163 @@
+use crate::session::SessionStore;
+use crate::Result;
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use grammers_session::Session;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+const STRING_SESSION_PREFIX: &str = "teleser1:";
+
+/// Callback receiving the exported string session when the session is saved
+pub type StringSessionCallback = Box<dyn Fn(&str) -> Result<()> + Send + Sync>;
+
+/// Export the session as a portable string, keep it as secret as the session file
+pub fn export_string_session(session: &Session) -> String {
+    encode_string_session(&session.save())
+}
+
+pub fn import_string_session(string_session: &str) -> Result<Session> {
+    Ok(Session::load(&decode_string_session(string_session)?)?)
+}
+
+fn encode_string_session(data: &[u8]) -> String {
+    format!("{STRING_SESSION_PREFIX}{}", URL_SAFE_NO_PAD.encode(data))
+}
+
+fn decode_string_session(string_session: &str) -> Result<Vec<u8>> {
+    let encoded = string_session
+        .trim()
+        .strip_prefix(STRING_SESSION_PREFIX)
+        .ok_or_else(|| anyhow!("not a teleser string session"))?;
+    URL_SAFE_NO_PAD
+        .decode(encoded)
+        .context("malformed string session")
+}
+
+/// Keep the session in memory, optionally writing it back through a callback
+pub struct MemorySessionStore {
+    data: Mutex<Option<Vec<u8>>>,
+    on_save: Option<StringSessionCallback>,
+}
+
+impl Default for MemorySessionStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemorySessionStore {
+    pub fn new() -> Self {
+        Self {
+            data: Mutex::new(None),
+            on_save: None,
+        }
+    }
+
+    pub fn from_string_session(string_session: &str) -> Result<Self> {
+        Ok(Self {
+            data: Mutex::new(Some(decode_string_session(string_session)?)),
+            on_save: None,
+        })
+    }
+
+    /// Start with a session, like one imported from Telethon or Pyrogram
+    pub fn from_session(session: &Session) -> Self {
+        Self {
+            data: Mutex::new(Some(session.save())),
+            on_save: None,
+        }
+    }
+
+    /// Called with the exported string session on every save
+    pub fn with_on_save(mut self, on_save: StringSessionCallback) -> Self {
+        self.on_save = Some(on_save);
+        self
+    }
+
+    /// The current session as a string session
+    pub fn string_session(&self) -> Option<String> {
+        self.data
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|data| encode_string_session(data))
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemorySessionStore {
+    async fn on_save_session(&self, data: Vec<u8>) -> Result<()> {
+        if let Some(on_save) = &self.on_save {
+            on_save(&encode_string_session(&data))?;
+        }
+        *self.data.lock().unwrap() = Some(data);
+        Ok(())
+    }
+
+    async fn on_load_session(&self) -> Result<Option<Vec<u8>>> {
+        Ok(self.data.lock().unwrap().clone())
+    }
+
+    async fn on_clear_session(&self) -> Result<()> {
+        *self.data.lock().unwrap() = None;
+        Ok(())
+    }
+}
+
+/// Read the string session from an environment variable (like a container secret).
+///
+/// The variable is never written, sessions saved later are kept in memory and
+/// passed to the optional callback. Once cleared, the variable is not read again.
+pub struct EnvSessionStore {
+    name: String,
+    memory: MemorySessionStore,
+    cleared: AtomicBool,
+}
+
+impl EnvSessionStore {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self {
+            name: name.into(),
+            memory: MemorySessionStore::new(),
+            cleared: AtomicBool::new(false),
+        }
+    }
+
+    /// Called with the exported string session on every save
+    pub fn with_on_save(mut self, on_save: StringSessionCallback) -> Self {
+        self.memory = self.memory.with_on_save(on_save);
+        self
+    }
+}
+
+#[async_trait]
+impl SessionStore for EnvSessionStore {
+    async fn on_save_session(&self, data: Vec<u8>) -> Result<()> {
+        self.memory.on_save_session(data).await
+    }
+
+    async fn on_load_session(&self) -> Result<Option<Vec<u8>>> {
+        if let Some(data) = self.memory.on_load_session().await? {
+            return Ok(Some(data));
+        }
+        if self.cleared.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        match std::env::var(&self.name) {
+            Ok(value) if !value.trim().is_empty() => {
+                Ok(Some(decode_string_session(&value).with_context(|| {
+                    format!("read session from env {}", self.name)
+                })?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn on_clear_session(&self) -> Result<()> {
+        self.cleared.store(true, Ordering::Relaxed);
+        self.memory.on_clear_session().await
+    }
+}
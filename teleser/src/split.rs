@@ -0,0 +1,329 @@
+use grammers_client::InputMessage;
+use grammers_tl_types as tl;
+
+/// Longest text of a message, in UTF-16 code units like Telegram counts
+pub const MAX_MESSAGE_LENGTH: usize = 4096;
+/// Longest caption of a media message, in UTF-16 code units
+pub const MAX_CAPTION_LENGTH: usize = 1024;
+
+/// Text with its formatting entities, entity offsets are in UTF-16 code units
+///
+/// ```
+/// use teleser::FormattedText;
+/// let text = FormattedText::plain("first paragraph\n\nsecond paragraph");
+/// let parts = text.split(20);
+/// assert_eq!(parts[0].text, "first paragraph");
+/// assert_eq!(parts[1].text, "second paragraph");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FormattedText {
+    pub text: String,
+    pub entities: Vec<tl::enums::MessageEntity>,
+}
+
+impl FormattedText {
+    pub fn new<S: Into<String>>(text: S, entities: Vec<tl::enums::MessageEntity>) -> Self {
+        Self {
+            text: text.into(),
+            entities,
+        }
+    }
+
+    pub fn plain<S: Into<String>>(text: S) -> Self {
+        Self::new(text, vec![])
+    }
+
+    #[cfg(feature = "markdown")]
+    pub fn markdown(markdown: &str) -> Self {
+        let (text, entities) = grammers_client::parsers::parse_markdown_message(markdown);
+        Self::new(text, entities)
+    }
+
+    #[cfg(feature = "html")]
+    pub fn html(html: &str) -> Self {
+        let (text, entities) = grammers_client::parsers::parse_html_message(html);
+        Self::new(text, entities)
+    }
+
+    /// Length in UTF-16 code units
+    pub fn len(&self) -> usize {
+        self.text.encode_utf16().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Split into parts of at most `limit` UTF-16 code units.
+    ///
+    /// Breaks after a paragraph, else a line, else a word, and only inside a code block,
+    /// a link or a mention if it is longer than `limit`. An entity over a break is
+    /// repeated in both parts, so the formatting is kept.
+    ///
+    /// ```
+    /// use teleser::FormattedText;
+    /// use teleser::re_exports::grammers_tl_types as tl;
+    ///
+    /// // the emoji takes 2 UTF-16 units, so the bold starts at 3
+    /// let bold = tl::types::MessageEntityBold { offset: 3, length: 9 }.into();
+    /// let parts = FormattedText::new("😀 aaaa bbbb", vec![bold]).split(8);
+    /// assert_eq!(parts[0].text, "😀 aaaa");
+    /// assert_eq!(parts[0].entities[0].offset(), 3);
+    /// assert_eq!(parts[0].entities[0].length(), 4);
+    /// assert_eq!(parts[1].text, "bbbb");
+    /// assert_eq!(parts[1].entities[0].offset(), 0);
+    /// assert_eq!(parts[1].entities[0].length(), 4);
+    ///
+    /// // a code block fitting a part is moved to the next part instead of split
+    /// let pre = tl::types::MessageEntityPre {
+    ///     offset: 5,
+    ///     length: 19,
+    ///     language: "rust".into(),
+    /// };
+    /// let parts = FormattedText::new("see:\nfn a() {}\nfn b() {}", vec![pre.into()]).split(20);
+    /// assert_eq!(parts[0].text, "see:");
+    /// assert!(parts[0].entities.is_empty());
+    /// assert_eq!(parts[1].text, "fn a() {}\nfn b() {}");
+    /// assert_eq!(parts[1].entities[0].offset(), 0);
+    /// assert_eq!(parts[1].entities[0].length(), 19);
+    /// ```
+    pub fn split(&self, limit: usize) -> Vec<FormattedText> {
+        self.split_with(limit, limit)
+    }
+
+    /// Split a caption too long for a media message: the first part fits the caption
+    /// of the media, the rest is sent as text messages after it
+    ///
+    /// ```
+    /// use teleser::{FormattedText, MAX_CAPTION_LENGTH, MAX_MESSAGE_LENGTH};
+    /// let (caption, rest) = FormattedText::plain("word ".repeat(2400)).split_caption();
+    /// assert!(caption.len() <= MAX_CAPTION_LENGTH && caption.len() > MAX_CAPTION_LENGTH - 10);
+    /// assert_eq!(rest.len(), 3);
+    /// assert!(rest.iter().all(|part| part.len() <= MAX_MESSAGE_LENGTH));
+    /// assert!(rest[0].len() > MAX_MESSAGE_LENGTH - 10);
+    /// ```
+    pub fn split_caption(&self) -> (FormattedText, Vec<FormattedText>) {
+        let mut parts = self
+            .split_with(MAX_CAPTION_LENGTH, MAX_MESSAGE_LENGTH)
+            .into_iter();
+        let caption = parts.next().unwrap_or_default();
+        (caption, parts.collect())
+    }
+
+    fn split_with(&self, first_limit: usize, limit: usize) -> Vec<FormattedText> {
+        Splitter::new(self).split(first_limit.max(2), limit.max(2))
+    }
+}
+
+impl From<FormattedText> for InputMessage {
+    fn from(text: FormattedText) -> Self {
+        InputMessage::text(text.text).fmt_entities(text.entities)
+    }
+}
+
+impl From<&str> for FormattedText {
+    fn from(text: &str) -> Self {
+        Self::plain(text)
+    }
+}
+
+impl From<String> for FormattedText {
+    fn from(text: String) -> Self {
+        Self::plain(text)
+    }
+}
+
+struct Splitter<'a> {
+    text: &'a FormattedText,
+    chars: Vec<char>,
+    // UTF-16 and byte offset of every char index, plus the end
+    utf16: Vec<usize>,
+    bytes: Vec<usize>,
+    // char ranges of the entities
+    ranges: Vec<(usize, usize)>,
+}
+
+impl<'a> Splitter<'a> {
+    fn new(text: &'a FormattedText) -> Self {
+        let (mut utf16, mut bytes, mut chars) = (vec![0], vec![0], vec![]);
+        for (byte, c) in text.text.char_indices() {
+            chars.push(c);
+            utf16.push(utf16[utf16.len() - 1] + c.len_utf16());
+            bytes.push(byte + c.len_utf8());
+        }
+        let ranges = text
+            .entities
+            .iter()
+            .map(|entity| {
+                let start = entity.offset().max(0) as usize;
+                let end = start + entity.length().max(0) as usize;
+                (
+                    utf16.partition_point(|offset| *offset < start),
+                    utf16.partition_point(|offset| *offset < end),
+                )
+            })
+            .collect();
+        Self {
+            text,
+            chars,
+            utf16,
+            bytes,
+            ranges,
+        }
+    }
+
+    fn split(&self, first_limit: usize, limit: usize) -> Vec<FormattedText> {
+        let mut parts = vec![];
+        let mut limit_now = first_limit;
+        let mut start = 0;
+        while start < self.chars.len() {
+            let max_end = self
+                .utf16
+                .partition_point(|offset| *offset <= self.utf16[start] + limit_now)
+                - 1;
+            let end = if max_end >= self.chars.len() {
+                self.chars.len()
+            } else {
+                self.break_before(start, max_end)
+            };
+            if let Some(part) = self.part(start, end) {
+                parts.push(part);
+                limit_now = limit;
+            }
+            start = end;
+        }
+        parts
+    }
+
+    /// The best char index to end a part starting at `start` and ending by `max_end`
+    fn break_before(&self, start: usize, max_end: usize) -> usize {
+        // a char longer than the limit still moves forward
+        if max_end <= start {
+            return start + 1;
+        }
+        let paragraph = |p: usize| p >= 2 && self.chars[p - 1] == '\n' && self.chars[p - 2] == '\n';
+        let line = |p: usize| self.chars[p - 1] == '\n';
+        let word = |p: usize| self.chars[p - 1].is_whitespace();
+        let levels: [&dyn Fn(usize) -> bool; 3] = [&paragraph, &line, &word];
+        for keep_entities in [true, false] {
+            for level in levels {
+                let found = (start + 1..=max_end)
+                    .rev()
+                    .find(|p| level(*p) && !(keep_entities && self.inside_unsplittable(*p)));
+                if let Some(p) = found {
+                    return p;
+                }
+            }
+            if let Some(p) = (start + 1..=max_end)
+                .rev()
+                .find(|p| !(keep_entities && self.inside_unsplittable(*p)))
+            {
+                return p;
+            }
+        }
+        max_end
+    }
+
+    fn inside_unsplittable(&self, p: usize) -> bool {
+        self.text
+            .entities
+            .iter()
+            .zip(&self.ranges)
+            .any(|(entity, (start, end))| *start < p && p < *end && !is_formatting(entity))
+    }
+
+    /// The text and the clipped entities of the chars `start..end`, without the
+    /// line breaks before and the whitespace after
+    fn part(&self, mut start: usize, mut end: usize) -> Option<FormattedText> {
+        while start < end && matches!(self.chars[start], '\n' | '\r') {
+            start += 1;
+        }
+        while end > start && self.chars[end - 1].is_whitespace() {
+            end -= 1;
+        }
+        if start == end {
+            return None;
+        }
+        let base = self.utf16[start];
+        let entities = self
+            .text
+            .entities
+            .iter()
+            .zip(&self.ranges)
+            .filter_map(|(entity, (entity_start, entity_end))| {
+                let (clip_start, clip_end) = ((*entity_start).max(start), (*entity_end).min(end));
+                (clip_start < clip_end).then(|| {
+                    with_range(
+                        entity.clone(),
+                        (self.utf16[clip_start] - base) as i32,
+                        (self.utf16[clip_end] - self.utf16[clip_start]) as i32,
+                    )
+                })
+            })
+            .collect();
+        Some(FormattedText::new(
+            &self.text.text[self.bytes[start]..self.bytes[end]],
+            entities,
+        ))
+    }
+}
+
+/// Entities only changing the style, fine to repeat over two messages
+fn is_formatting(entity: &tl::enums::MessageEntity) -> bool {
+    use tl::enums::MessageEntity as E;
+    matches!(
+        entity,
+        E::Bold(_)
+            | E::Italic(_)
+            | E::Underline(_)
+            | E::Strike(_)
+            | E::Spoiler(_)
+            | E::Blockquote(_)
+            | E::TextUrl(_)
+    )
+}
+
+macro_rules! with_range {
+    ($entity:expr, $offset:expr, $length:expr, $($variant:ident),* $(,)?) => {
+        match &mut $entity {
+            $(tl::enums::MessageEntity::$variant(e) => {
+                e.offset = $offset;
+                e.length = $length;
+            })*
+        }
+    };
+}
+
+fn with_range(
+    mut entity: tl::enums::MessageEntity,
+    offset: i32,
+    length: i32,
+) -> tl::enums::MessageEntity {
+    with_range!(
+        entity,
+        offset,
+        length,
+        Unknown,
+        Mention,
+        Hashtag,
+        BotCommand,
+        Url,
+        Email,
+        Bold,
+        Italic,
+        Code,
+        Pre,
+        TextUrl,
+        MentionName,
+        InputMessageEntityMentionName,
+        Phone,
+        Cashtag,
+        Underline,
+        Strike,
+        BankCard,
+        Spoiler,
+        CustomEmoji,
+        Blockquote,
+    );
+    entity
+}
@@ -1,5 +1,18 @@
+use crate::{CallbackData, ClientHandle, Result, SendPriority};
 use async_trait::async_trait;
-use grammers_client::types::{Chat, Message};
+use grammers_client::types::{CallbackQuery, Chat, InputReactions, Message};
+use grammers_client::InputMessage;
+use grammers_session::PackedChat;
+use grammers_tl_types as tl;
+use std::cell::Cell;
+use std::path::Path;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+tokio::task_local! {
+    // set by the `CallbackQueryExt` answers during the dispatch of a callback query
+    pub(crate) static CALLBACK_ANSWERED: Cell<bool>;
+}
 
 pub trait ChatExt {
     fn is_user(&self) -> bool;
@@ -32,9 +45,61 @@ impl ChatExt for Chat {
     }
 }
 
+/// Actions on a message through a `ClientHandle`, so they are rate limited and retried.
+/// Replies go with `SendPriority::High`.
 #[async_trait]
 pub trait MessageExt {
     fn has_sender(&self) -> bool;
+
+    async fn reply_text<S: Into<String> + Send>(
+        &self,
+        client: &ClientHandle,
+        text: S,
+    ) -> Result<Message>;
+
+    #[cfg(feature = "html")]
+    async fn reply_html<S: Into<String> + Send>(
+        &self,
+        client: &ClientHandle,
+        html: S,
+    ) -> Result<Message>;
+
+    #[cfg(feature = "markdown")]
+    async fn reply_markdown<S: Into<String> + Send>(
+        &self,
+        client: &ClientHandle,
+        markdown: S,
+    ) -> Result<Message>;
+
+    /// Upload the photo at `path` and reply with it
+    async fn reply_photo<P: AsRef<Path> + Send, S: Into<String> + Send>(
+        &self,
+        client: &ClientHandle,
+        path: P,
+        caption: S,
+    ) -> Result<Message>;
+
+    async fn forward_to<C: Into<PackedChat> + Send>(
+        &self,
+        client: &ClientHandle,
+        chat: C,
+    ) -> Result<Option<Message>>;
+
+    /// Delete the message in the background after `delay`, abort the task to keep it
+    fn delete_after(&self, client: &ClientHandle, delay: Duration) -> JoinHandle<()>;
+
+    /// Edit the message if it was sent by this account, else reply.
+    /// Returns the reply, `None` when edited.
+    async fn edit_or_reply<S: Into<String> + Send>(
+        &self,
+        client: &ClientHandle,
+        text: S,
+    ) -> Result<Option<Message>>;
+
+    /// React with an emoji, an empty emoji removes the reactions of this account
+    async fn react(&self, client: &ClientHandle, emoji: &str) -> Result<()>;
+
+    async fn pin(&self, client: &ClientHandle) -> Result<()>;
 }
 
 impl ChatExt for Message {
@@ -56,4 +121,207 @@ impl MessageExt for Message {
     fn has_sender(&self) -> bool {
         self.sender().is_some()
     }
+
+    async fn reply_text<S: Into<String> + Send>(
+        &self,
+        client: &ClientHandle,
+        text: S,
+    ) -> Result<Message> {
+        let text = text.into();
+        reply(self, client, || InputMessage::text(&text)).await
+    }
+
+    #[cfg(feature = "html")]
+    async fn reply_html<S: Into<String> + Send>(
+        &self,
+        client: &ClientHandle,
+        html: S,
+    ) -> Result<Message> {
+        let html = html.into();
+        reply(self, client, || InputMessage::html(&html)).await
+    }
+
+    #[cfg(feature = "markdown")]
+    async fn reply_markdown<S: Into<String> + Send>(
+        &self,
+        client: &ClientHandle,
+        markdown: S,
+    ) -> Result<Message> {
+        let markdown = markdown.into();
+        reply(self, client, || InputMessage::markdown(&markdown)).await
+    }
+
+    async fn reply_photo<P: AsRef<Path> + Send, S: Into<String> + Send>(
+        &self,
+        client: &ClientHandle,
+        path: P,
+        caption: S,
+    ) -> Result<Message> {
+        let uploaded = client.client().await?.upload_file(path).await?;
+        let caption = caption.into();
+        reply(self, client, || {
+            InputMessage::text(&caption).photo(uploaded.clone())
+        })
+        .await
+    }
+
+    async fn forward_to<C: Into<PackedChat> + Send>(
+        &self,
+        client: &ClientHandle,
+        chat: C,
+    ) -> Result<Option<Message>> {
+        let mut forwarded = client
+            .forward_messages(chat, &[self.id()], self.chat())
+            .await?;
+        Ok(forwarded.pop().flatten())
+    }
+
+    fn delete_after(&self, client: &ClientHandle, delay: Duration) -> JoinHandle<()> {
+        let (client, chat, id) = (client.clone(), self.chat().pack(), self.id());
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            if let Err(err) = client.delete_messages(chat, &[id]).await {
+                tracing::warn!("delete message {id} error : {err}");
+            }
+        })
+    }
+
+    async fn edit_or_reply<S: Into<String> + Send>(
+        &self,
+        client: &ClientHandle,
+        text: S,
+    ) -> Result<Option<Message>> {
+        let text = text.into();
+        if self.outgoing() {
+            client
+                .edit_message(self.chat(), self.id(), text.as_str())
+                .await?;
+            return Ok(None);
+        }
+        Ok(Some(
+            reply(self, client, || InputMessage::text(&text)).await?,
+        ))
+    }
+
+    async fn react(&self, client: &ClientHandle, emoji: &str) -> Result<()> {
+        let reactions = match emoji {
+            "" => InputReactions::remove(),
+            emoji => InputReactions::emoticon(emoji),
+        };
+        let (chat, id) = (self.chat().pack(), self.id());
+        client
+            .call(|inner| {
+                let reactions = reactions.clone();
+                async move { inner.send_reactions(chat, id, reactions).await }
+            })
+            .await
+    }
+
+    async fn pin(&self, client: &ClientHandle) -> Result<()> {
+        let (chat, id) = (self.chat().pack(), self.id());
+        client
+            .call(|inner| async move { inner.pin_message(chat, id).await })
+            .await
+    }
+}
+
+/// Send the message built by `input` as a reply, `input` is called again on retry
+async fn reply<F>(message: &Message, client: &ClientHandle, input: F) -> Result<Message>
+where
+    F: Fn() -> InputMessage + Send + Sync,
+{
+    let (chat, id) = (message.chat().pack(), message.id());
+    client.wait_send_slot(chat, SendPriority::High).await;
+    client
+        .call_send(|inner| {
+            let message = input().reply_to(Some(id));
+            async move { inner.send_message(chat, message).await }
+        })
+        .await
+}
+
+/// Answers to a callback query through a `ClientHandle`.
+///
+/// Answered in the handler (not in a spawned task), the query is not answered again
+/// by `ClientBuilder::with_auto_answer_callback`.
+#[async_trait]
+pub trait CallbackQueryExt {
+    /// Read the data of the button as typed data
+    fn data_as<T: CallbackData>(&self) -> Result<T>;
+
+    /// Only stop the spinner of the button
+    async fn answer_empty(&self, client: &ClientHandle) -> Result<()>;
+
+    /// A small popup fading on its own
+    async fn answer_toast<S: Into<String> + Send>(
+        &self,
+        client: &ClientHandle,
+        text: S,
+    ) -> Result<()>;
+
+    /// A popup the user has to close
+    async fn answer_alert<S: Into<String> + Send>(
+        &self,
+        client: &ClientHandle,
+        text: S,
+    ) -> Result<()>;
+
+    /// Open a URL, a game URL or a `t.me/<bot>?start=` link
+    async fn answer_url<S: Into<String> + Send>(&self, client: &ClientHandle, url: S)
+        -> Result<()>;
+}
+
+#[async_trait]
+impl CallbackQueryExt for CallbackQuery {
+    fn data_as<T: CallbackData>(&self) -> Result<T> {
+        T::from_data(self.raw.data.as_deref().unwrap_or_default())
+    }
+
+    async fn answer_empty(&self, client: &ClientHandle) -> Result<()> {
+        answer_callback(self, client, None, false, None).await
+    }
+
+    async fn answer_toast<S: Into<String> + Send>(
+        &self,
+        client: &ClientHandle,
+        text: S,
+    ) -> Result<()> {
+        answer_callback(self, client, Some(text.into()), false, None).await
+    }
+
+    async fn answer_alert<S: Into<String> + Send>(
+        &self,
+        client: &ClientHandle,
+        text: S,
+    ) -> Result<()> {
+        answer_callback(self, client, Some(text.into()), true, None).await
+    }
+
+    async fn answer_url<S: Into<String> + Send>(
+        &self,
+        client: &ClientHandle,
+        url: S,
+    ) -> Result<()> {
+        answer_callback(self, client, None, false, Some(url.into())).await
+    }
+}
+
+async fn answer_callback(
+    query: &CallbackQuery,
+    client: &ClientHandle,
+    message: Option<String>,
+    alert: bool,
+    url: Option<String>,
+) -> Result<()> {
+    client
+        .invoke(&tl::functions::messages::SetBotCallbackAnswer {
+            alert,
+            query_id: query.raw.query_id,
+            message,
+            url,
+            cache_time: 0,
+        })
+        .await?;
+    let _ = CALLBACK_ANSWERED.try_with(|answered| answered.set(true));
+    Ok(())
 }
@@ -697,3 +697,99 @@ pub fn raw(_: TokenStream, input: TokenStream) -> TokenStream {
         #build_into
     })
 }
+
+/// `#[scheduled(Schedule::interval(Duration::from_secs(60)))]`, the argument is the `Schedule`
+#[proc_macro_error]
+#[proc_macro_attribute]
+pub fn scheduled(args: TokenStream, input: TokenStream) -> TokenStream {
+    let schedule = parse_macro_input!(args as syn::Expr);
+    // must append to async fn
+    let method = parse_macro_input!(input as syn::ItemFn);
+    if method.sig.asyncness.is_none() {
+        abort!(&method.sig.span(), "must be async function");
+    }
+    // params check
+    let params = &method.sig.inputs;
+    if params.len() != 1 {
+        abort!(&method.sig.span(), "must be 1 param");
+    };
+    let param = params.first().unwrap();
+    let param = match param {
+        FnArg::Receiver(_) => abort!(&param.span(), "do not input self"),
+        FnArg::Typed(pt) => pt,
+    };
+    let param_pat = param.pat.as_ref();
+    let param_ty = param.ty.as_ref();
+    let param_ty = quote! {#param_ty};
+    let param_ty_str: String = param_ty.to_string();
+    if !param_ty_str.ends_with("ClientHandle") {
+        abort!(
+            param.span(),
+            format!(
+                "unknown param type {}, please modify to ClientHandle",
+                param_ty_str
+            )
+        );
+    }
+    let trait_name = quote! {::teleser::ScheduledProcess};
+    let enum_name = quote! {::teleser::Process::ScheduledProcess};
+    // result
+    let rs = method.sig.output;
+    // gen token stream
+    let ident = &method.sig.ident;
+    let ident_str = format!("{}", ident);
+    let build_struct = quote! {
+        #[allow(non_camel_case_types)]
+        pub struct #ident {}
+    };
+    let block = &method.block;
+    let build_trait = quote! {
+        #[::teleser::re_exports::async_trait::async_trait]
+        impl #trait_name for #ident {
+            fn schedule(&self) -> ::teleser::Schedule {
+                #schedule
+            }
+
+            async fn handle(&self, #param_pat: #param_ty) #rs #block
+        }
+    };
+
+    let build_into = quote! {
+
+        impl Into<::teleser::Process> for #ident {
+            fn into(self) -> ::teleser::Process {
+                #enum_name(Box::new(self))
+            }
+        }
+
+        impl Into<::teleser::Handler> for #ident {
+            fn into(self) -> ::teleser::Handler {
+                ::teleser::Handler {
+                    id: #ident_str.to_owned(),
+                    process: self.into(),
+                }
+            }
+        }
+
+        impl Into<Vec<::teleser::Handler>> for #ident {
+            fn into(self) -> Vec<::teleser::Handler> {
+                vec![self.into()]
+            }
+        }
+
+        impl Into<::teleser::Module> for #ident {
+            fn into(self) -> ::teleser::Module {
+                ::teleser::Module {
+                    id: #ident_str.to_owned(),
+                    name: #ident_str.to_owned(),
+                    handlers: vec![self.into()],
+                }
+            }
+        }
+    };
+    emit!(quote! {
+        #build_struct
+        #build_trait
+        #build_into
+    })
+}
@@ -2,15 +2,16 @@ mod proc_message_deleted;
 mod proc_message_edited;
 mod proc_new_message;
 mod raw_plugin;
+mod timer_plugin;
 
-use std::io::{stdin, stdout, Write};
 use std::sync::Arc;
-use teleser::re_exports::async_trait::async_trait;
+use std::time::Duration;
 use teleser::re_exports::grammers_client::InitParams;
 use teleser::re_exports::tokio;
 use teleser::re_exports::tokio::runtime;
+use teleser::re_exports::tracing;
 use teleser::re_exports::tracing::Level;
-use teleser::{Auth, AuthWithPhoneAndCode, FileSessionStore, Result};
+use teleser::{Auth, FileSessionStore, Result, TerminalAuth};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
@@ -45,16 +46,20 @@ async fn async_main() -> Result<()> {
         teleser::ClientBuilder::new()
             .with_api_id(env!("API_ID").parse()?)
             .with_api_hash(env!("API_HASH").to_string())
-            .with_auth(Auth::AuthWithPhoneAndCode(Box::new(Input {})))
-            .with_session_store(Box::new(FileSessionStore {
-                path: "teleser.session".to_string(),
-            }))
+            .with_auth(Auth::AuthWithPhoneAndCode(Box::new(TerminalAuth::new())))
+            .with_session_store(Box::new(FileSessionStore::new("teleser.session")))
             .with_modules(vec![
                 raw_plugin::module(),
                 proc_new_message::module(),
                 proc_message_edited::module(),
                 proc_message_deleted::module(),
+                // runs every hour once authorized
+                timer_plugin::module(),
             ])
+            // fetch the updates missed while offline, up to one day
+            .with_catch_up_window(Some(Duration::from_secs(60 * 60 * 24)))
+            // save the session every minute if it changed (auth keys, dc, update state)
+            .with_session_save_interval(Some(Duration::from_secs(60)))
             .with_init_params(match std::env::var("TELESER_PROXY") {
                 Ok(url) => {
                     let mut ip = InitParams::default();
@@ -67,13 +72,12 @@ async fn async_main() -> Result<()> {
     );
     //////////////////////////////////////
     // can start some timer task like this
-    let copy_client = client.clone();
+    // the handle waits until the client is connected and authorized
+    let handle = client.handle();
     tokio::spawn(async move {
-        let lock = copy_client.inner_client.lock().await;
-        let ic = lock.clone();
-        drop(lock);
-        if let Some(_ic) = ic {
-            // _ic.send_message();
+        if let Ok(me) = handle.get_me().await {
+            // handle.send_message(&me, "started").await;
+            tracing::info!("timer task started as {}", me.id());
         }
     });
     //////////////////////////////////////
@@ -82,34 +86,3 @@ async fn async_main() -> Result<()> {
     /////////////////////////////////////
     Ok(())
 }
-
-pub struct Input {}
-
-#[async_trait]
-impl AuthWithPhoneAndCode for Input {
-    async fn input_phone(&self) -> Result<String> {
-        input("Input your phone number ( like +112345678 )")
-    }
-
-    async fn input_code(&self) -> Result<String> {
-        input("Input your device or sms code ( like 12345 )")
-    }
-
-    async fn input_password(&self) -> Result<String> {
-        input("Input your password")
-    }
-}
-
-fn input(tips: &str) -> Result<String> {
-    let mut s = String::new();
-    print!("{}: ", tips);
-    let _ = stdout().flush();
-    stdin().read_line(&mut s)?;
-    if let Some('\n') = s.chars().next_back() {
-        s.pop();
-    }
-    if let Some('\r') = s.chars().next_back() {
-        s.pop();
-    }
-    Ok(s)
-}
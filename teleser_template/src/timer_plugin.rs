@@ -0,0 +1,22 @@
+use crate::Result;
+use std::time::Duration;
+use teleser::re_exports::tracing;
+use teleser::{scheduled, ClientHandle, Handler, Module, Schedule};
+
+#[scheduled(Schedule::interval(Duration::from_secs(60 * 60)))]
+async fn timer_plugin(client: ClientHandle) -> Result<()> {
+    let me = client.get_me().await?;
+    tracing::info!("TIMER : still running as {}", me.id());
+    Ok(())
+}
+
+pub(crate) fn module() -> Module {
+    Module {
+        id: "timer_plugin".to_owned(),
+        name: "timer_plugin".to_owned(),
+        handlers: vec![Handler {
+            id: "timer_plugin".to_owned(),
+            process: timer_plugin {}.into(),
+        }],
+    }
+}